@@ -0,0 +1,295 @@
+use crate::midi::{
+    self, MidiBackend, MidiError, MidiEvent, MidiIn, MidiMsg, MidiOut, MidiResult, PortCaps,
+};
+use alsa::{poll, seq, Direction};
+use std::ffi::CString;
+use std::mem;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+
+const CLIENT_NAME: &str = "launchpad";
+const POLL_TIMEOUT_MS: i32 = 100;
+
+pub struct AlsaMidi;
+
+impl MidiBackend for AlsaMidi {
+    type InCaps = AlsaCaps;
+    type OutCaps = AlsaCaps;
+    type InDev = InDev;
+    type OutDev = OutDev;
+
+    fn enumerate_in() -> Vec<Self::InCaps> {
+        enumerate_ports(seq::PortCap::READ | seq::PortCap::SUBS_READ)
+    }
+
+    fn enumerate_out() -> Vec<Self::OutCaps> {
+        enumerate_ports(seq::PortCap::WRITE | seq::PortCap::SUBS_WRITE)
+    }
+
+    fn open_in(in_caps: &Self::InCaps) -> MidiResult<Self::InDev> {
+        InDev::new(in_caps.addr)
+    }
+
+    fn open_out(out_caps: &Self::OutCaps) -> MidiResult<Self::OutDev> {
+        OutDev::new(out_caps.addr)
+    }
+}
+
+impl From<alsa::Error> for MidiError {
+    fn from(err: alsa::Error) -> Self {
+        let text = err.to_string();
+        match err.errno() {
+            libc::EBUSY => MidiError::Allocated(text),
+            libc::ENOENT | libc::ENXIO => MidiError::BadDeviceId(text),
+            libc::ENODEV => MidiError::NoDevice(text),
+            libc::EACCES | libc::EPERM => MidiError::NotEnabled(text),
+            libc::EBADF => MidiError::InvalidHandle(text),
+            libc::ENOMEM => MidiError::NoMem(text),
+            libc::EAGAIN => MidiError::NotReady(text),
+            _ => MidiError::Other(text),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct AlsaCaps {
+    pub addr: seq::Addr,
+    pub name: String,
+    pub id: String,
+}
+
+impl PortCaps for AlsaCaps {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+fn open_seq() -> MidiResult<seq::Seq> {
+    let seq = seq::Seq::open(None, None, true)?;
+    seq.set_client_name(&CString::new(CLIENT_NAME).unwrap())?;
+    Ok(seq)
+}
+
+fn enumerate_ports(caps: seq::PortCap) -> Vec<AlsaCaps> {
+    let seq = match open_seq() {
+        Ok(seq) => seq,
+        Err(_) => return Vec::new(),
+    };
+    let own_id = seq.client_id().ok();
+
+    // Client and card numbers follow the plug order, so identical devices are told apart
+    // by the USB port of their card.
+    let ports: Vec<(seq::Addr, String, (String, Option<String>))> = seq::ClientIter::new(&seq)
+        .filter(|client| Some(client.get_client()) != own_id)
+        .flat_map(|client| {
+            let client_name = client.get_name().unwrap_or_default().to_owned();
+            let location = card_location(client.get_client());
+            seq::PortIter::new(&seq, client.get_client())
+                .filter(|port| port.get_capability().contains(caps))
+                .map(|port| {
+                    let name = port.get_name().unwrap_or_default().to_owned();
+                    let id = format!("{}:{}", client_name, port.get_port());
+                    (port.addr(), name, (id, location.clone()))
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect();
+
+    let ids = midi::port_ids(ports.iter().map(|(_, _, id)| id.clone()).collect());
+    ports
+        .into_iter()
+        .zip(ids)
+        .map(|((addr, name, _), id)| AlsaCaps { addr, name, id })
+        .collect()
+}
+
+/// Where the card of a kernel client is connected, e.g. `usb-0000:00:14.0-2`.
+///
+/// The kernel gives the clients of card `n` the numbers from `16 + 4 * n`, clients of
+/// applications start at 128.
+fn card_location(client: i32) -> Option<String> {
+    if !(16..128).contains(&client) {
+        return None;
+    }
+    let longname = alsa::Card::new((client - 16) / 4).get_longname().ok()?;
+    // "Focusrite A.E. Ltd Launchpad Mini at usb-0000:00:14.0-2, full speed"
+    let (_, location) = longname.rsplit_once(" at ")?;
+    let location = location.split(',').next()?.trim();
+    Some(location.to_owned()).filter(|location| !location.is_empty())
+}
+
+pub struct InDev {
+    msg_rx: mpsc::Receiver<MidiMsg>,
+    started: Arc<Mutex<Option<Instant>>>,
+    alive: Arc<AtomicBool>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl InDev {
+    fn new(src: seq::Addr) -> MidiResult<Self> {
+        let seq = open_seq()?;
+        let port = seq.create_simple_port(
+            &CString::new("in").unwrap(),
+            seq::PortCap::WRITE | seq::PortCap::SUBS_WRITE,
+            seq::PortType::MIDI_GENERIC | seq::PortType::APPLICATION,
+        )?;
+
+        let subs = seq::PortSubscribe::empty()?;
+        subs.set_sender(src);
+        subs.set_dest(seq::Addr {
+            client: seq.client_id()?,
+            port,
+        });
+        seq.subscribe_port(&subs)?;
+
+        let (msg_tx, msg_rx) = mpsc::channel::<MidiMsg>();
+        let started = Arc::new(Mutex::new(None));
+        let alive = Arc::new(AtomicBool::new(true));
+
+        let started_c = started.clone();
+        let alive_c = alive.clone();
+        let thread = thread::spawn(move || in_thread(seq, msg_tx, started_c, alive_c));
+
+        Ok(Self {
+            msg_rx,
+            started,
+            alive,
+            thread: Some(thread),
+        })
+    }
+}
+
+fn in_thread(
+    seq: seq::Seq,
+    msg_tx: mpsc::Sender<MidiMsg>,
+    started: Arc<Mutex<Option<Instant>>>,
+    alive: Arc<AtomicBool>,
+) {
+    let decoder = match seq::MidiEvent::new(16) {
+        Ok(decoder) => decoder,
+        Err(_) => return,
+    };
+    decoder.enable_running_status(false);
+
+    let mut input = seq.input();
+    let mut buf = [0; 16];
+    let mut sysex = Vec::new();
+    while alive.load(Ordering::Relaxed) {
+        if poll::poll_all(&[&(&seq, Some(Direction::Capture))], POLL_TIMEOUT_MS).is_err() {
+            break;
+        }
+
+        while input.event_input_pending(true).unwrap_or(0) > 0 {
+            let mut event = match input.event_input() {
+                Ok(event) => event,
+                Err(_) => break,
+            };
+            let started = match *started.lock().unwrap() {
+                Some(started) => started,
+                None => continue,
+            };
+
+            let event = if event.get_type() == seq::EventType::Sysex {
+                // The sequencer splits long SysEx messages into several events.
+                let chunk = event.get_ext().unwrap_or_default();
+                if chunk.first() == Some(&0xF0) {
+                    sysex.clear();
+                }
+                sysex.extend_from_slice(chunk);
+                if sysex.last() != Some(&0xF7) {
+                    continue;
+                }
+                MidiEvent::SysEx(mem::take(&mut sysex))
+            } else if let Ok(len @ 1..=3) = decoder.decode(&mut buf, &mut event) {
+                let mut data = [0; 3];
+                data[..len].copy_from_slice(&buf[..len]);
+                match MidiEvent::from_short(data) {
+                    Some(event) => event,
+                    None => continue,
+                }
+            } else {
+                continue;
+            };
+
+            let msg = MidiMsg {
+                timestamp: started.elapsed(),
+                event,
+            };
+            if msg_tx.send(msg).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+impl MidiIn for InDev {
+    fn start(&mut self) -> MidiResult<()> {
+        self.started.lock().unwrap().replace(Instant::now());
+        Ok(())
+    }
+
+    fn stop(&mut self) -> MidiResult<()> {
+        self.started.lock().unwrap().take();
+        Ok(())
+    }
+
+    fn receiver(&self) -> &mpsc::Receiver<MidiMsg> {
+        &self.msg_rx
+    }
+}
+
+impl Drop for InDev {
+    fn drop(&mut self) {
+        self.alive.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.join().unwrap();
+        }
+    }
+}
+
+pub struct OutDev {
+    seq: seq::Seq,
+    port: i32,
+    dest: seq::Addr,
+}
+
+impl OutDev {
+    fn new(dest: seq::Addr) -> MidiResult<Self> {
+        let seq = open_seq()?;
+        let port = seq.create_simple_port(
+            &CString::new("out").unwrap(),
+            seq::PortCap::READ | seq::PortCap::SUBS_READ,
+            seq::PortType::MIDI_GENERIC | seq::PortType::APPLICATION,
+        )?;
+        Ok(Self { seq, port, dest })
+    }
+}
+
+impl MidiOut for OutDev {
+    fn send(&mut self, msg: u8, dw1: u8, dw2: u8) -> MidiResult<()> {
+        let mut encoder = seq::MidiEvent::new(16)?;
+        encoder.enable_running_status(false);
+        if let (_, Some(mut event)) = encoder.encode(&[msg, dw1, dw2])? {
+            event.set_source(self.port);
+            event.set_dest(self.dest);
+            event.set_direct();
+            self.seq.event_output_direct(&mut event)?;
+        }
+        Ok(())
+    }
+
+    fn send_sysex(&mut self, data: &[u8]) -> MidiResult<()> {
+        let mut event = seq::Event::new_ext(seq::EventType::Sysex, data);
+        event.set_source(self.port);
+        event.set_dest(self.dest);
+        event.set_direct();
+        self.seq.event_output_direct(&mut event)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,228 @@
+use crate::launchpad::{Color, Frame, LaunchpadOut, LaunchpadOutBuf, LaunchpadResult, PadColor};
+use crate::pad::{Coord, Orientation, Pad};
+use std::convert::TryFrom;
+
+/// A button of a canvas.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CanvasPad {
+    /// A cell of the combined grid, `x` counted from the left and `y` from the top.
+    Grid { x: u16, y: u16 },
+    /// A top or side button of one device, which stay per device.
+    Button { device: usize, pad: Pad },
+}
+
+/// Where the grid of a device lies on the canvas.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Placement {
+    /// Position of the top left cell as seen by the user, the device has to end before
+    /// `u16::MAX`.
+    pub x: u16,
+    pub y: u16,
+    /// How the device is mounted, applied by the device itself.
+    pub orientation: Orientation,
+}
+
+impl Placement {
+    /// The canvas button of a `pad` of the `device` placed here.
+    pub fn to_canvas(self, device: usize, pad: Pad) -> CanvasPad {
+        match pad {
+            Pad::Grid { x, y } => CanvasPad::Grid {
+                x: self.x + x.get() as u16,
+                y: self.y + y.get() as u16,
+            },
+            _ => CanvasPad::Button { device, pad },
+        }
+    }
+
+    /// The grid pad of the device placed here that shows the canvas cell at `x`, `y`.
+    fn to_device(self, x: u16, y: u16) -> Option<Pad> {
+        let coord = |val: u16, start: u16| {
+            let val = u8::try_from(val.checked_sub(start)?).ok()?;
+            Coord::new(val)
+        };
+        Some(Pad::Grid {
+            x: coord(x, self.x)?,
+            y: coord(y, self.y)?,
+        })
+    }
+}
+
+struct CanvasDevice {
+    placement: Placement,
+    out_buf: LaunchpadOutBuf,
+}
+
+/// Several Launchpads combined into one grid.
+///
+/// Every device keeps its colors while detached, like `LaunchpadOutBuf`. Where placements
+/// overlap the device added first wins, cells no device covers are ignored.
+pub struct Canvas {
+    devices: Vec<CanvasDevice>,
+}
+
+impl Canvas {
+    pub fn new() -> Self {
+        Self {
+            devices: Vec::new(),
+        }
+    }
+
+    /// Adds a device slot and returns its index.
+    pub fn add_device(&mut self, placement: Placement) -> usize {
+        self.devices.push(CanvasDevice {
+            placement,
+            out_buf: LaunchpadOutBuf::new(),
+        });
+        self.devices.len() - 1
+    }
+
+    pub fn placement(&self, device: usize) -> Placement {
+        self.devices[device].placement
+    }
+
+    /// Width of the grid, counting gaps between devices.
+    pub fn width(&self) -> u16 {
+        self.devices
+            .iter()
+            .map(|device| device.placement.x.saturating_add(8))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Height of the grid, counting gaps between devices.
+    pub fn height(&self) -> u16 {
+        self.devices
+            .iter()
+            .map(|device| device.placement.y.saturating_add(8))
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn attach(&mut self, device: usize, mut out_pad: LaunchpadOut) -> LaunchpadResult<()> {
+        let device = &mut self.devices[device];
+        out_pad.set_orientation(device.placement.orientation);
+        device.out_buf.attach(out_pad)
+    }
+
+    pub fn detach(&mut self, device: usize) -> Option<LaunchpadOut> {
+        self.devices[device].out_buf.detach()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_attached(&self, device: usize) -> bool {
+        self.devices[device].out_buf.is_attached()
+    }
+
+    /// Whether a device shows the grid cell at `x`, `y`.
+    pub fn covers(&self, x: u16, y: u16) -> bool {
+        self.locate(CanvasPad::Grid { x, y }).is_some()
+    }
+
+    pub fn set_brightness(&mut self, percent: u8) -> LaunchpadResult<()> {
+        self.each(|out_buf| out_buf.set_brightness(percent))
+    }
+
+    #[allow(dead_code)]
+    pub fn clear(&mut self) -> LaunchpadResult<()> {
+        self.each(|out_buf| out_buf.clear())
+    }
+
+    #[allow(dead_code)]
+    pub fn get_color(&self, pad: CanvasPad) -> PadColor {
+        match self.locate(pad) {
+            Some((device, pad)) => self.devices[device].out_buf.get_color(pad),
+            None => Color::BLACK.into(),
+        }
+    }
+
+    pub fn set_color(&mut self, pad: CanvasPad, col: impl Into<PadColor>) -> LaunchpadResult<()> {
+        match self.locate(pad) {
+            Some((device, pad)) => self.devices[device].out_buf.set_color(pad, col),
+            None => Ok(()),
+        }
+    }
+
+    pub fn frame(&mut self) -> CanvasFrame<'_> {
+        let frames = self
+            .devices
+            .iter_mut()
+            .map(|device| (device.placement, device.out_buf.frame()))
+            .collect();
+        CanvasFrame { frames }
+    }
+
+    /// The device showing a canvas pad and the pad on it.
+    fn locate(&self, pad: CanvasPad) -> Option<(usize, Pad)> {
+        locate(self.devices.iter().map(|device| device.placement), pad)
+    }
+
+    /// Runs `f` on every device, returning the first error after all ran.
+    fn each<F>(&mut self, mut f: F) -> LaunchpadResult<()>
+    where
+        F: FnMut(&mut LaunchpadOutBuf) -> LaunchpadResult<()>,
+    {
+        let mut result = Ok(());
+        for device in self.devices.iter_mut() {
+            let res = f(&mut device.out_buf);
+            result = result.and(res);
+        }
+        result
+    }
+}
+
+fn locate<I>(placements: I, pad: CanvasPad) -> Option<(usize, Pad)>
+where
+    I: Iterator<Item = Placement>,
+{
+    match pad {
+        CanvasPad::Grid { x, y } => placements
+            .enumerate()
+            .find_map(|(device, placement)| Some((device, placement.to_device(x, y)?))),
+        CanvasPad::Button { device, pad } => {
+            let count = placements.count();
+            Some((device, pad)).filter(|_| device < count)
+        }
+    }
+}
+
+/// A `Frame` for every device of a canvas, committed together.
+pub struct CanvasFrame<'a> {
+    frames: Vec<(Placement, Frame<'a>)>,
+}
+
+impl CanvasFrame<'_> {
+    #[allow(dead_code)]
+    pub fn get_color(&self, pad: CanvasPad) -> PadColor {
+        match self.locate(pad) {
+            Some((device, pad)) => self.frames[device].1.get_color(pad),
+            None => Color::BLACK.into(),
+        }
+    }
+
+    pub fn set_color(&mut self, pad: CanvasPad, col: impl Into<PadColor>) {
+        if let Some((device, pad)) = self.locate(pad) {
+            self.frames[device].1.set_color(pad, col);
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn clear(&mut self) {
+        for (_, frame) in self.frames.iter_mut() {
+            frame.clear();
+        }
+    }
+
+    /// Commits every device's frame, returning the first error after all were tried.
+    pub fn commit(self) -> LaunchpadResult<()> {
+        let mut result = Ok(());
+        for (_, frame) in self.frames {
+            let res = frame.commit();
+            result = result.and(res);
+        }
+        result
+    }
+
+    fn locate(&self, pad: CanvasPad) -> Option<(usize, Pad)> {
+        locate(self.frames.iter().map(|(placement, _)| *placement), pad)
+    }
+}
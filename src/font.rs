@@ -0,0 +1,183 @@
+use crate::launchpad::{Color, Frame, PadColor};
+use crate::pad::Pad;
+use std::convert::TryFrom;
+use std::ops::RangeInclusive;
+
+/// Width of a space, other glyphs are as wide as their lit columns.
+const SPACE_WIDTH: usize = 3;
+
+/// Glyphs for printable ASCII, one byte per column with the top row in the lowest bit.
+///
+/// Glyphs are at most 5 columns wide and 7 rows high, which leaves the bottom row of the
+/// 8x8 grid free.
+#[rustfmt::skip]
+const FONT: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], [0x00, 0x00, 0x5F, 0x00, 0x00], // space !
+    [0x00, 0x07, 0x00, 0x07, 0x00], [0x14, 0x7F, 0x14, 0x7F, 0x14], // " #
+    [0x24, 0x2A, 0x7F, 0x2A, 0x12], [0x23, 0x13, 0x08, 0x64, 0x62], // $ %
+    [0x36, 0x49, 0x56, 0x20, 0x50], [0x00, 0x05, 0x03, 0x00, 0x00], // & '
+    [0x00, 0x1C, 0x22, 0x41, 0x00], [0x00, 0x41, 0x22, 0x1C, 0x00], // ( )
+    [0x14, 0x08, 0x3E, 0x08, 0x14], [0x08, 0x08, 0x3E, 0x08, 0x08], // * +
+    [0x00, 0x50, 0x30, 0x00, 0x00], [0x08, 0x08, 0x08, 0x08, 0x08], // , -
+    [0x00, 0x60, 0x60, 0x00, 0x00], [0x20, 0x10, 0x08, 0x04, 0x02], // . /
+    [0x3E, 0x51, 0x49, 0x45, 0x3E], [0x00, 0x42, 0x7F, 0x40, 0x00], // 0 1
+    [0x42, 0x61, 0x51, 0x49, 0x46], [0x21, 0x41, 0x45, 0x4B, 0x31], // 2 3
+    [0x18, 0x14, 0x12, 0x7F, 0x10], [0x27, 0x45, 0x45, 0x45, 0x39], // 4 5
+    [0x3C, 0x4A, 0x49, 0x49, 0x30], [0x01, 0x71, 0x09, 0x05, 0x03], // 6 7
+    [0x36, 0x49, 0x49, 0x49, 0x36], [0x06, 0x49, 0x49, 0x29, 0x1E], // 8 9
+    [0x00, 0x36, 0x36, 0x00, 0x00], [0x00, 0x56, 0x36, 0x00, 0x00], // : ;
+    [0x08, 0x14, 0x22, 0x41, 0x00], [0x14, 0x14, 0x14, 0x14, 0x14], // < =
+    [0x00, 0x41, 0x22, 0x14, 0x08], [0x02, 0x01, 0x51, 0x09, 0x06], // > ?
+    [0x32, 0x49, 0x79, 0x41, 0x3E], [0x7E, 0x11, 0x11, 0x11, 0x7E], // @ A
+    [0x7F, 0x49, 0x49, 0x49, 0x36], [0x3E, 0x41, 0x41, 0x41, 0x22], // B C
+    [0x7F, 0x41, 0x41, 0x22, 0x1C], [0x7F, 0x49, 0x49, 0x49, 0x41], // D E
+    [0x7F, 0x09, 0x09, 0x09, 0x01], [0x3E, 0x41, 0x49, 0x49, 0x7A], // F G
+    [0x7F, 0x08, 0x08, 0x08, 0x7F], [0x00, 0x41, 0x7F, 0x41, 0x00], // H I
+    [0x20, 0x40, 0x41, 0x3F, 0x01], [0x7F, 0x08, 0x14, 0x22, 0x41], // J K
+    [0x7F, 0x40, 0x40, 0x40, 0x40], [0x7F, 0x02, 0x0C, 0x02, 0x7F], // L M
+    [0x7F, 0x04, 0x08, 0x10, 0x7F], [0x3E, 0x41, 0x41, 0x41, 0x3E], // N O
+    [0x7F, 0x09, 0x09, 0x09, 0x06], [0x3E, 0x41, 0x51, 0x21, 0x5E], // P Q
+    [0x7F, 0x09, 0x19, 0x29, 0x46], [0x46, 0x49, 0x49, 0x49, 0x31], // R S
+    [0x01, 0x01, 0x7F, 0x01, 0x01], [0x3F, 0x40, 0x40, 0x40, 0x3F], // T U
+    [0x1F, 0x20, 0x40, 0x20, 0x1F], [0x3F, 0x40, 0x38, 0x40, 0x3F], // V W
+    [0x63, 0x14, 0x08, 0x14, 0x63], [0x07, 0x08, 0x70, 0x08, 0x07], // X Y
+    [0x61, 0x51, 0x49, 0x45, 0x43], [0x00, 0x7F, 0x41, 0x41, 0x00], // Z [
+    [0x02, 0x04, 0x08, 0x10, 0x20], [0x00, 0x41, 0x41, 0x7F, 0x00], // \ ]
+    [0x04, 0x02, 0x01, 0x02, 0x04], [0x40, 0x40, 0x40, 0x40, 0x40], // ^ _
+    [0x00, 0x01, 0x02, 0x04, 0x00], [0x20, 0x54, 0x54, 0x54, 0x78], // ` a
+    [0x7F, 0x48, 0x44, 0x44, 0x38], [0x38, 0x44, 0x44, 0x44, 0x20], // b c
+    [0x38, 0x44, 0x44, 0x48, 0x7F], [0x38, 0x54, 0x54, 0x54, 0x18], // d e
+    [0x08, 0x7E, 0x09, 0x01, 0x02], [0x0C, 0x52, 0x52, 0x52, 0x3E], // f g
+    [0x7F, 0x08, 0x04, 0x04, 0x78], [0x00, 0x44, 0x7D, 0x40, 0x00], // h i
+    [0x20, 0x40, 0x44, 0x3D, 0x00], [0x7F, 0x10, 0x28, 0x44, 0x00], // j k
+    [0x00, 0x41, 0x7F, 0x40, 0x00], [0x7C, 0x04, 0x18, 0x04, 0x78], // l m
+    [0x7C, 0x08, 0x04, 0x04, 0x78], [0x38, 0x44, 0x44, 0x44, 0x38], // n o
+    [0x7C, 0x14, 0x14, 0x14, 0x08], [0x08, 0x14, 0x14, 0x18, 0x7C], // p q
+    [0x7C, 0x08, 0x04, 0x04, 0x08], [0x48, 0x54, 0x54, 0x54, 0x20], // r s
+    [0x04, 0x3F, 0x44, 0x40, 0x20], [0x3C, 0x40, 0x40, 0x20, 0x7C], // t u
+    [0x1C, 0x20, 0x40, 0x20, 0x1C], [0x3C, 0x40, 0x30, 0x40, 0x3C], // v w
+    [0x44, 0x28, 0x10, 0x28, 0x44], [0x0C, 0x50, 0x50, 0x50, 0x3C], // x y
+    [0x44, 0x64, 0x54, 0x4C, 0x44], [0x00, 0x08, 0x36, 0x41, 0x00], // z {
+    [0x00, 0x00, 0x7F, 0x00, 0x00], [0x00, 0x41, 0x36, 0x08, 0x00], // | }
+    [0x10, 0x08, 0x08, 0x10, 0x08],                                 // ~
+];
+
+/// The lit columns of a character, `?` for anything but printable ASCII.
+pub fn glyph(c: char) -> &'static [u8] {
+    let index = match c {
+        ' '..='~' => c as usize - ' ' as usize,
+        _ => '?' as usize - ' ' as usize,
+    };
+    let columns = &FONT[index];
+    match columns.iter().position(|&column| column != 0) {
+        Some(start) => {
+            let end = columns.iter().rposition(|&column| column != 0).unwrap();
+            &columns[start..=end]
+        }
+        None => &columns[..SPACE_WIDTH],
+    }
+}
+
+/// A string laid out with proportional spacing, ready to be drawn at any scroll offset.
+#[allow(dead_code)]
+pub struct Text {
+    columns: Vec<u8>,
+}
+
+#[allow(dead_code)]
+impl Text {
+    pub fn new(text: &str) -> Self {
+        let mut columns = Vec::new();
+        for (i, c) in text.chars().enumerate() {
+            if i > 0 {
+                columns.push(0);
+            }
+            columns.extend_from_slice(glyph(c));
+        }
+        Self { columns }
+    }
+
+    pub fn width(&self) -> usize {
+        self.columns.len()
+    }
+
+    /// Draws the text onto the grid with its column `offset` at the left edge.
+    ///
+    /// Grid cells the text doesn't light are turned off, the buttons around it are kept.
+    pub fn draw(&self, frame: &mut Frame, offset: i32, col: impl Into<PadColor>) {
+        let col = col.into();
+        for x in 0..8 {
+            let column = usize::try_from(offset + x as i32)
+                .ok()
+                .and_then(|index| self.columns.get(index))
+                .copied()
+                .unwrap_or(0);
+            for y in 0..8 {
+                let lit = column & (1 << y) != 0;
+                let pad = Pad::grid(x, y).unwrap();
+                frame.set_color(pad, if lit { col } else { Color::BLACK.into() });
+            }
+        }
+    }
+
+    /// Offsets that scroll the text in from the right until it has left on the left.
+    pub fn scroll_offsets(&self) -> RangeInclusive<i32> {
+        -8..=self.width() as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::launchpad::LaunchpadOutBuf;
+
+    fn lit(frame: &Frame) -> Vec<(u8, u8)> {
+        let cells = (0..8).flat_map(|y| (0..8).map(move |x| (x, y)));
+        cells
+            .filter(|&(x, y)| !frame.get_color(Pad::grid(x, y).unwrap()).is_off())
+            .collect()
+    }
+
+    #[test]
+    fn glyphs_are_trimmed() {
+        assert_eq!(glyph('I'), [0x41, 0x7F, 0x41]);
+        assert_eq!(glyph('!'), [0x5F]);
+        assert_eq!(glyph(' '), [0x00; SPACE_WIDTH]);
+        assert_eq!(glyph('é'), glyph('?'));
+    }
+
+    #[test]
+    fn glyphs_are_one_column_apart() {
+        let text = Text::new("I! ");
+        assert_eq!(
+            text.columns,
+            [0x41, 0x7F, 0x41, 0x00, 0x5F, 0x00, 0x00, 0x00, 0x00]
+        );
+        assert_eq!(text.width(), 9);
+        assert_eq!(text.scroll_offsets(), -8..=9);
+        assert_eq!(Text::new("").width(), 0);
+    }
+
+    #[test]
+    fn draw_clears_the_grid_and_keeps_the_buttons() {
+        let mut out_buf = LaunchpadOutBuf::new();
+        let mut frame = out_buf.frame();
+        for pad in Pad::all() {
+            frame.set_color(pad, Color::GREEN);
+        }
+        let text = Text::new("!");
+
+        // Scrolling in from the right.
+        text.draw(&mut frame, -6, Color::RED);
+        let column: Vec<_> = [0, 1, 2, 3, 4, 6].iter().map(|&y| (6, y)).collect();
+        assert_eq!(lit(&frame), column);
+        assert_eq!(frame.get_color(Pad::grid(6, 0).unwrap()), Color::RED.into());
+        assert_eq!(frame.get_color(Pad::UP), Color::GREEN.into());
+        assert_eq!(frame.get_color(Pad::side(7).unwrap()), Color::GREEN.into());
+
+        // Gone on the left.
+        text.draw(&mut frame, 1, Color::RED);
+        assert_eq!(lit(&frame), []);
+        assert_eq!(frame.get_color(Pad::UP), Color::GREEN.into());
+    }
+}
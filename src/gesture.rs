@@ -0,0 +1,333 @@
+use crate::launchpad::{Event, LaunchpadIn};
+use crate::pad::Pad;
+use std::collections::VecDeque;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::{Duration, Instant};
+
+/// Thresholds of the gesture recognizer.
+#[derive(Clone, Copy, Debug)]
+pub struct GestureConfig {
+    /// How long a pad has to be held to count as a long press.
+    pub long_press: Duration,
+    /// The longest time from releasing a tap to pressing the same pad again for a double tap.
+    pub double_tap: Duration,
+    /// Time between repeats while a pad stays held after a long press.
+    pub repeat_interval: Duration,
+}
+
+impl Default for GestureConfig {
+    fn default() -> Self {
+        Self {
+            long_press: Duration::from_millis(500),
+            double_tap: Duration::from_millis(300),
+            repeat_interval: Duration::from_millis(150),
+        }
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Gesture {
+    /// A pad was released before it became a long press.
+    Tap(Pad),
+    /// A pad was tapped again right after a tap, reported instead of the second `Tap`.
+    DoubleTap(Pad),
+    /// A pad has been held alone for the long-press time.
+    LongPress(Pad),
+    /// A long-pressed pad is still held.
+    Repeat(Pad),
+    /// Several pads were held together, reported in press order when the first is released.
+    Chord(Vec<Pad>),
+}
+
+struct Held {
+    pad: Pad,
+    since: Duration,
+    long_pressed: bool,
+    next_repeat: Duration,
+}
+
+/// Turns timestamped `Up`/`Down` events into gestures.
+///
+/// Timestamps have to come from one clock, such as those of `LaunchpadIn::timed_msgs`. Long
+/// presses and repeats are due while no events arrive, so `poll` has to be called with the
+/// current time of that clock, see `next_deadline`.
+pub struct Recognizer {
+    config: GestureConfig,
+    held: Vec<Held>,
+    in_chord: bool,
+    chord_reported: bool,
+    last_tap: Option<(Pad, Duration)>,
+}
+
+impl Recognizer {
+    pub fn new(config: GestureConfig) -> Self {
+        Self {
+            config,
+            held: Vec::new(),
+            in_chord: false,
+            chord_reported: false,
+            last_tap: None,
+        }
+    }
+
+    pub fn feed(&mut self, timestamp: Duration, event: Event) -> Vec<Gesture> {
+        let mut gestures = self.poll(timestamp);
+        match event {
+            Event::Down(pad) => {
+                if self.held.iter().any(|held| held.pad == pad) {
+                    return gestures;
+                }
+                self.held.push(Held {
+                    pad,
+                    since: timestamp,
+                    long_pressed: false,
+                    next_repeat: timestamp + self.config.long_press,
+                });
+                if self.held.len() > 1 {
+                    self.in_chord = true;
+                }
+            }
+            Event::Up(pad) => {
+                let index = match self.held.iter().position(|held| held.pad == pad) {
+                    Some(index) => index,
+                    None => return gestures,
+                };
+                if self.in_chord && !self.chord_reported {
+                    self.chord_reported = true;
+                    let pads = self.held.iter().map(|held| held.pad).collect();
+                    gestures.push(Gesture::Chord(pads));
+                }
+                let held = self.held.remove(index);
+
+                if !self.in_chord && !held.long_pressed {
+                    match self.last_tap.take() {
+                        Some((last, released))
+                            if last == pad && held.since <= released + self.config.double_tap =>
+                        {
+                            gestures.push(Gesture::DoubleTap(pad));
+                        }
+                        _ => {
+                            self.last_tap = Some((pad, timestamp));
+                            gestures.push(Gesture::Tap(pad));
+                        }
+                    }
+                }
+
+                if self.held.is_empty() {
+                    self.in_chord = false;
+                    self.chord_reported = false;
+                }
+            }
+            Event::TextScrolled => (),
+        }
+        gestures
+    }
+
+    /// Reports the long presses and repeats due at `now`.
+    pub fn poll(&mut self, now: Duration) -> Vec<Gesture> {
+        let mut gestures = Vec::new();
+        if self.in_chord {
+            return gestures;
+        }
+        let repeat_interval = self.config.repeat_interval;
+        for held in self.held.iter_mut() {
+            while held.next_repeat <= now {
+                gestures.push(if held.long_pressed {
+                    Gesture::Repeat(held.pad)
+                } else {
+                    Gesture::LongPress(held.pad)
+                });
+                held.long_pressed = true;
+                held.next_repeat += repeat_interval;
+            }
+        }
+        gestures
+    }
+
+    /// When `poll` will report something next, if anything is held.
+    pub fn next_deadline(&self) -> Option<Duration> {
+        if self.in_chord {
+            return None;
+        }
+        self.held.iter().map(|held| held.next_repeat).min()
+    }
+}
+
+/// Gestures of a `LaunchpadIn`, keeping track of its input clock.
+pub struct Gestures {
+    in_pad: LaunchpadIn,
+    recognizer: Recognizer,
+    pending: VecDeque<Gesture>,
+    /// When the input clock was at zero, estimated from the earliest event seen.
+    started: Option<Instant>,
+}
+
+impl Gestures {
+    pub fn new(in_pad: LaunchpadIn, config: GestureConfig) -> Self {
+        Self {
+            in_pad,
+            recognizer: Recognizer::new(config),
+            pending: VecDeque::new(),
+            started: None,
+        }
+    }
+
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<Gesture, RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(gesture) = self.pending.pop_front() {
+                return Ok(gesture);
+            }
+
+            let due = match (self.started, self.recognizer.next_deadline()) {
+                (Some(started), Some(next)) => Some(started + next),
+                _ => None,
+            };
+            let until = due.map_or(deadline, |due| due.min(deadline));
+            let timeout = until.saturating_duration_since(Instant::now());
+            match self.in_pad.recv_timed_timeout(timeout) {
+                Ok((timestamp, event)) => {
+                    let now = Instant::now();
+                    let started = now.checked_sub(timestamp).unwrap_or(now);
+                    if self.started.is_none_or(|s| started < s) {
+                        self.started = Some(started);
+                    }
+                    let gestures = self.recognizer.feed(timestamp, event);
+                    self.pending.extend(gestures);
+                }
+                Err(RecvTimeoutError::Timeout) if until < deadline => {
+                    let now = self.started.map_or(Duration::from_secs(0), |s| s.elapsed());
+                    let gestures = self.recognizer.poll(now);
+                    self.pending.extend(gestures);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pad::Coord;
+
+    fn pad(x: u8) -> Pad {
+        Pad::Grid {
+            x: Coord::new(x).unwrap(),
+            y: Coord::new(0).unwrap(),
+        }
+    }
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn recognizer() -> Recognizer {
+        Recognizer::new(GestureConfig::default())
+    }
+
+    #[test]
+    fn tap() {
+        let mut rec = recognizer();
+        assert_eq!(rec.feed(ms(0), Event::Down(pad(0))), vec![]);
+        assert_eq!(
+            rec.feed(ms(100), Event::Up(pad(0))),
+            vec![Gesture::Tap(pad(0))]
+        );
+        assert_eq!(rec.next_deadline(), None);
+    }
+
+    #[test]
+    fn double_tap() {
+        let mut rec = recognizer();
+        rec.feed(ms(0), Event::Down(pad(0)));
+        rec.feed(ms(100), Event::Up(pad(0)));
+        rec.feed(ms(400), Event::Down(pad(0)));
+        let gestures = rec.feed(ms(450), Event::Up(pad(0)));
+        assert_eq!(gestures, vec![Gesture::DoubleTap(pad(0))]);
+
+        // A third tap starts over.
+        rec.feed(ms(500), Event::Down(pad(0)));
+        let gestures = rec.feed(ms(550), Event::Up(pad(0)));
+        assert_eq!(gestures, vec![Gesture::Tap(pad(0))]);
+    }
+
+    #[test]
+    fn slow_or_other_second_tap() {
+        let mut rec = recognizer();
+        rec.feed(ms(0), Event::Down(pad(0)));
+        rec.feed(ms(100), Event::Up(pad(0)));
+        rec.feed(ms(401), Event::Down(pad(0)));
+        let gestures = rec.feed(ms(450), Event::Up(pad(0)));
+        assert_eq!(gestures, vec![Gesture::Tap(pad(0))]);
+
+        rec.feed(ms(500), Event::Down(pad(1)));
+        let gestures = rec.feed(ms(550), Event::Up(pad(1)));
+        assert_eq!(gestures, vec![Gesture::Tap(pad(1))]);
+    }
+
+    #[test]
+    fn long_press_and_repeat() {
+        let mut rec = recognizer();
+        rec.feed(ms(0), Event::Down(pad(0)));
+        assert_eq!(rec.next_deadline(), Some(ms(500)));
+        assert_eq!(rec.poll(ms(499)), vec![]);
+        assert_eq!(rec.poll(ms(500)), vec![Gesture::LongPress(pad(0))]);
+        assert_eq!(rec.next_deadline(), Some(ms(650)));
+        assert_eq!(rec.poll(ms(650)), vec![Gesture::Repeat(pad(0))]);
+        assert_eq!(rec.feed(ms(700), Event::Up(pad(0))), vec![]);
+        assert_eq!(rec.next_deadline(), None);
+    }
+
+    #[test]
+    fn late_poll_catches_up() {
+        let mut rec = recognizer();
+        rec.feed(ms(0), Event::Down(pad(0)));
+        let gestures = rec.poll(ms(800));
+        let expected = vec![
+            Gesture::LongPress(pad(0)),
+            Gesture::Repeat(pad(0)),
+            Gesture::Repeat(pad(0)),
+        ];
+        assert_eq!(gestures, expected);
+    }
+
+    #[test]
+    fn release_reports_due_long_press() {
+        let mut rec = recognizer();
+        rec.feed(ms(0), Event::Down(pad(0)));
+        let gestures = rec.feed(ms(600), Event::Up(pad(0)));
+        assert_eq!(gestures, vec![Gesture::LongPress(pad(0))]);
+    }
+
+    #[test]
+    fn chord() {
+        let mut rec = recognizer();
+        rec.feed(ms(0), Event::Down(pad(1)));
+        rec.feed(ms(10), Event::Down(pad(0)));
+        assert_eq!(rec.next_deadline(), None);
+        assert_eq!(rec.poll(ms(1000)), vec![]);
+        let gestures = rec.feed(ms(1010), Event::Up(pad(0)));
+        assert_eq!(gestures, vec![Gesture::Chord(vec![pad(1), pad(0)])]);
+        assert_eq!(rec.feed(ms(1020), Event::Up(pad(1))), vec![]);
+
+        // Once everything is released single presses count again.
+        rec.feed(ms(1100), Event::Down(pad(0)));
+        let gestures = rec.feed(ms(1150), Event::Up(pad(0)));
+        assert_eq!(gestures, vec![Gesture::Tap(pad(0))]);
+    }
+
+    #[test]
+    fn stray_events() {
+        let mut rec = recognizer();
+        assert_eq!(rec.feed(ms(0), Event::Up(pad(0))), vec![]);
+        rec.feed(ms(10), Event::Down(pad(0)));
+        rec.feed(ms(20), Event::Down(pad(0)));
+        assert_eq!(
+            rec.feed(ms(30), Event::Up(pad(0))),
+            vec![Gesture::Tap(pad(0))]
+        );
+        assert_eq!(rec.feed(ms(40), Event::TextScrolled), vec![]);
+    }
+}
@@ -0,0 +1,50 @@
+use crate::launchpad::{self, UninitLaunchpad};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+pub enum DeviceEvent {
+    Arrived(UninitLaunchpad),
+    /// Carries the id of the removed device.
+    Removed(String),
+}
+
+/// Polls the connected Launchpads every `interval` and reports the differences.
+///
+/// Devices already present when the watcher starts are reported as arrived.
+pub fn watch_launchpads(interval: Duration) -> mpsc::Receiver<DeviceEvent> {
+    let (tx, rx) = mpsc::channel();
+    #[cfg(test)]
+    let registry = crate::mock_midi::Registry::current();
+    thread::spawn(move || {
+        #[cfg(test)]
+        registry.enter();
+        let mut known: Vec<String> = Vec::new();
+        loop {
+            let present: Vec<UninitLaunchpad> = launchpad::enumerate_launchpads().collect();
+
+            let mut events = Vec::new();
+            for id in known.iter() {
+                if !present.iter().any(|pad| pad.id() == id) {
+                    events.push(DeviceEvent::Removed(id.clone()));
+                }
+            }
+
+            let ids = present.iter().map(|pad| pad.id().to_owned()).collect();
+            for pad in present {
+                if !known.iter().any(|id| id == pad.id()) {
+                    events.push(DeviceEvent::Arrived(pad));
+                }
+            }
+            known = ids;
+
+            for event in events {
+                if tx.send(event).is_err() {
+                    return;
+                }
+            }
+            thread::sleep(interval);
+        }
+    });
+    rx
+}
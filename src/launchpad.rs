@@ -1,157 +1,984 @@
-use crate::win_midi as midi;
-use crate::win_midi_sys as sys;
+use crate::midi::{self, DeviceIdentity, MidiBackend, MidiEvent, MidiIn, MidiOut, PortCaps};
+use crate::pad::{Coord, Orientation, Pad};
+use std::cell::Cell;
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use thiserror::Error;
-use winapi::um::mmsystem::MM_MIM_DATA as IN_DATA;
 
 #[derive(Error, Debug)]
 pub enum LaunchpadError {
     #[error(transparent)]
-    MidiError(#[from] sys::MidiError),
-    #[error("Position ({0}, {1}) is out of range")]
-    OutOfRange(u8, u8),
+    MidiError(#[from] midi::MidiError),
+    #[error("The {0:?} isn't supported")]
+    UnsupportedModel(Model),
 }
 
 pub type LaunchpadResult<T> = Result<T, LaunchpadError>;
 
+const INQUIRY_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Velocity flags of the red/green models: clear the LED in the other buffer
+/// and write it to both buffers.
+const FLAG_CLEAR: u8 = 0x08;
+const FLAG_COPY: u8 = 0x04;
+
+/// Pairs every Launchpad input port with the output port of the same device.
 pub fn enumerate_launchpads() -> impl Iterator<Item = UninitLaunchpad> {
-    midi::enumerate_midi_in().filter_map(|in_caps| {
-        if !in_caps.name.contains("Launchpad") {
-            return None;
-        }
+    let mut outs = midi::Backend::enumerate_out();
+    midi::Backend::enumerate_in()
+        .into_iter()
+        .filter(|in_caps| is_launchpad_port(in_caps.name()))
+        .filter_map(|in_caps| {
+            let index = outs
+                .iter()
+                .position(|out_caps| out_caps.id() == in_caps.id())?;
+            let out_caps = outs.remove(index);
+            Some(UninitLaunchpad {
+                in_caps,
+                out_caps,
+                model: Cell::new(None),
+            })
+        })
+        .collect::<Vec<_>>()
+        .into_iter()
+}
 
-        midi::enumerate_midi_out()
-            .find(|out_caps| in_caps.matches(out_caps))
-            .map(|out_caps| UninitLaunchpad { in_caps, out_caps })
-    })
+/// Newer models name their ports "LPX MIDI" or "LPMiniMK3 MIDI" and also have a DAW port,
+/// which doesn't support programmer mode.
+fn is_launchpad_port(name: &str) -> bool {
+    (name.contains("Launchpad") || name.starts_with("LP"))
+        && !name.contains("DAW")
+        && !name.contains("DIN")
+}
+
+#[allow(dead_code)]
+pub fn find_launchpad(id: &str) -> Option<UninitLaunchpad> {
+    enumerate_launchpads().find(|pad| pad.id() == id)
 }
 
 pub struct UninitLaunchpad {
-    in_caps: sys::MidiInCaps,
-    out_caps: sys::MidiOutCaps,
+    in_caps: midi::InCaps,
+    out_caps: midi::OutCaps,
+    model: Cell<Option<Model>>,
 }
 
 impl UninitLaunchpad {
+    /// Opens the device and asks it for its model, which is remembered for later calls.
     pub fn init(&self) -> LaunchpadResult<(LaunchpadIn, LaunchpadOut)> {
-        Ok((
-            LaunchpadIn::new(self.in_caps.open()?)?,
-            LaunchpadOut::new(self.out_caps.open()?),
-        ))
+        let mut in_pad = LaunchpadIn::new(midi::Backend::open_in(&self.in_caps)?)?;
+        let mut out_pad = LaunchpadOut::new(midi::Backend::open_out(&self.out_caps)?);
+
+        let model = match self.model.get() {
+            Some(model) => model,
+            None => {
+                let model = query_model(&mut in_pad, &mut out_pad)?;
+                self.model.set(Some(model));
+                model
+            }
+        };
+        if !model.is_supported() {
+            return Err(LaunchpadError::UnsupportedModel(model));
+        }
+        in_pad.model = model;
+        in_pad.layout = out_pad.layout.clone();
+        in_pad.orientation = out_pad.orientation.clone();
+        out_pad.set_model(model)?;
+        Ok((in_pad, out_pad))
     }
 
-    #[allow(dead_code)]
     pub fn name(&self) -> &str {
-        &self.in_caps.name
+        self.in_caps.name()
+    }
+
+    pub fn id(&self) -> &str {
+        self.in_caps.id()
+    }
+
+    /// The model detected by the last `init`, `None` if the device wasn't opened yet.
+    pub fn model(&self) -> Option<Model> {
+        self.model.get()
+    }
+}
+
+fn query_model(in_pad: &mut LaunchpadIn, out_pad: &mut LaunchpadOut) -> LaunchpadResult<Model> {
+    out_pad.out_dev.send_sysex(&midi::DEVICE_INQUIRY)?;
+
+    let deadline = Instant::now() + INQUIRY_TIMEOUT;
+    loop {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        let msg = match in_pad.in_dev.receiver().recv_timeout(timeout) {
+            Ok(msg) => msg,
+            // The original Launchpad doesn't answer device inquiries.
+            Err(_) => return Ok(Model::Launchpad),
+        };
+        if let MidiEvent::SysEx(data) = msg.event {
+            if let Some(identity) = DeviceIdentity::parse(&data) {
+                return Ok(Model::from_identity(&identity));
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Model {
+    Launchpad,
+    LaunchpadS,
+    LaunchpadMini,
+    LaunchpadMk2,
+    LaunchpadPro,
+    LaunchpadX,
+    LaunchpadMiniMk3,
+    LaunchpadProMk3,
+    /// A device that answered with an unknown family code.
+    Unknown(u16),
+}
+
+impl Model {
+    const NOVATION: [u8; 3] = [0x00, 0x20, 0x29];
+
+    /// Novation reports the USB product id as the family code.
+    pub fn from_identity(identity: &DeviceIdentity) -> Self {
+        if identity.manufacturer != Self::NOVATION {
+            return Model::Unknown(identity.family);
+        }
+
+        match identity.family {
+            0x0020 => Model::LaunchpadS,
+            0x0036 => Model::LaunchpadMini,
+            0x0069 => Model::LaunchpadMk2,
+            0x0051 => Model::LaunchpadPro,
+            0x0103 => Model::LaunchpadX,
+            0x0113 => Model::LaunchpadMiniMk3,
+            0x0123 => Model::LaunchpadProMk3,
+            family => Model::Unknown(family),
+        }
+    }
+
+    /// Whether the model can be driven, the Mk2 and the Pro have layouts of their own.
+    pub fn is_supported(self) -> bool {
+        !matches!(self, Model::LaunchpadMk2 | Model::LaunchpadPro)
+    }
+
+    /// Whether the model uses the programmer mode layout and RGB colors.
+    pub fn is_rgb(self) -> bool {
+        self.sysex_device().is_some()
+    }
+
+    /// The device byte following the Novation header in SysEx messages.
+    fn sysex_device(self) -> Option<u8> {
+        match self {
+            Model::LaunchpadX => Some(0x0C),
+            Model::LaunchpadMiniMk3 => Some(0x0D),
+            Model::LaunchpadProMk3 => Some(0x0E),
+            _ => None,
+        }
+    }
+}
+
+/// Note layouts of the red/green models, RGB models always use the programmer layout.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Layout {
+    /// Notes count up by 16 per row from the top left.
+    XY,
+    /// Four notes per row in two columns of 4x4 blocks from the bottom left,
+    /// which lines up with the drum racks of Live.
+    DrumRack,
+}
+
+impl Layout {
+    /// Maps a pad to its status and note or controller.
+    fn message(self, pad: Pad) -> (u8, u8) {
+        match (self, pad) {
+            (Layout::XY, Pad::Grid { x, y }) => (0x90, y.get() * 16 + x.get()),
+            (Layout::XY, Pad::Side(y)) => (0x90, y.get() * 16 + 8),
+            (Layout::DrumRack, Pad::Grid { x, y }) => {
+                let row = 7 - y.get();
+                match x.get() {
+                    x @ 0..=3 => (0x90, 36 + row * 4 + x),
+                    x => (0x90, 68 + row * 4 + x - 4),
+                }
+            }
+            (Layout::DrumRack, Pad::Side(y)) => (0x90, 100 + y.get()),
+            (_, Pad::Top(x)) => (0xB0, 0x68 + x.get()),
+        }
+    }
+
+    fn note_pad(self, note: u8) -> Option<Pad> {
+        match self {
+            Layout::XY => {
+                let y = Coord::new(note / 16)?;
+                match note % 16 {
+                    8 => Some(Pad::Side(y)),
+                    x => Some(Pad::Grid {
+                        x: Coord::new(x)?,
+                        y,
+                    }),
+                }
+            }
+            Layout::DrumRack => match note {
+                36..=67 => Pad::grid((note - 36) % 4, 7 - (note - 36) / 4),
+                68..=99 => Pad::grid((note - 68) % 4 + 4, 7 - (note - 68) / 4),
+                100..=107 => Pad::side(note - 100),
+                _ => None,
+            },
+        }
+    }
+
+    fn controller_pad(controller: u8) -> Option<Pad> {
+        Pad::top(controller.checked_sub(0x68)?)
+    }
+
+    /// The value of the CC 0 message selecting the layout.
+    fn control(self) -> u8 {
+        match self {
+            Layout::XY => 0x01,
+            Layout::DrumRack => 0x02,
+        }
+    }
+}
+
+/// Maps a pad to its LED index in programmer mode, where the bottom left pad is 11.
+fn programmer_index(pad: Pad) -> u8 {
+    match pad {
+        Pad::Grid { x, y } => (8 - y.get()) * 10 + x.get() + 1,
+        Pad::Side(y) => (8 - y.get()) * 10 + 9,
+        Pad::Top(x) => 91 + x.get(),
+    }
+}
+
+fn programmer_pad(index: u8) -> Option<Pad> {
+    match (index % 10, index / 10) {
+        (1..=8, 9) => Pad::top(index % 10 - 1),
+        (1..=8, 1..=8) => Pad::grid(index % 10 - 1, 8 - index / 10),
+        (9, 1..=8) => Pad::side(8 - index / 10),
+        _ => None,
+    }
+}
+
+fn programmer_spec(index: u8, col: PadColor) -> Vec<u8> {
+    match col {
+        PadColor::Palette(palette) => vec![0x00, index, palette & 0x7F],
+        col => {
+            let Rgb { r, g, b } = col.into();
+            vec![0x03, index, r >> 1, g >> 1, b >> 1]
+        }
     }
 }
 
 pub struct LaunchpadIn {
     in_dev: midi::InDev,
+    model: Model,
+    layout: Arc<Mutex<Layout>>,
+    orientation: Arc<Mutex<Orientation>>,
 }
 
 impl LaunchpadIn {
     fn new(mut in_dev: midi::InDev) -> LaunchpadResult<Self> {
         in_dev.start()?;
-        Ok(Self { in_dev })
+        Ok(Self {
+            in_dev,
+            model: Model::Launchpad,
+            layout: Arc::new(Mutex::new(Layout::XY)),
+            orientation: Arc::new(Mutex::new(Orientation::default())),
+        })
+    }
+
+    #[allow(dead_code)]
+    pub fn model(&self) -> Model {
+        self.model
     }
 
     #[allow(dead_code)]
     pub fn current_msgs(&mut self) -> impl Iterator<Item = Event> + '_ {
-        Self::map_midi_msgs(self.in_dev.current_msgs())
+        Self::map_midi_msgs(
+            self.model,
+            self.layout.clone(),
+            self.orientation.clone(),
+            self.in_dev.current_msgs(),
+        )
+        .map(|(_, event)| event)
     }
 
     #[allow(dead_code)]
     pub fn msgs(&mut self) -> impl Iterator<Item = Event> + '_ {
-        Self::map_midi_msgs(self.in_dev.msgs())
+        Self::map_midi_msgs(
+            self.model,
+            self.layout.clone(),
+            self.orientation.clone(),
+            self.in_dev.msgs(),
+        )
+        .map(|(_, event)| event)
+    }
+
+    #[allow(dead_code)]
+    pub fn timed_msgs(&mut self) -> impl Iterator<Item = (Duration, Event)> + '_ {
+        Self::map_midi_msgs(
+            self.model,
+            self.layout.clone(),
+            self.orientation.clone(),
+            self.in_dev.msgs(),
+        )
+    }
+
+    #[allow(dead_code)]
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<Event, RecvTimeoutError> {
+        self.recv_timed_timeout(timeout).map(|(_, event)| event)
+    }
+
+    /// Like `recv_timeout`, with the timestamp of the event as in `timed_msgs`.
+    pub fn recv_timed_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<(Duration, Event), RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            let msg = self.in_dev.receiver().recv_timeout(timeout)?;
+            if let Some(timed) = Self::map_midi_msgs(
+                self.model,
+                self.layout.clone(),
+                self.orientation.clone(),
+                Some(msg).into_iter(),
+            )
+            .next()
+            {
+                return Ok(timed);
+            }
+        }
     }
 
-    fn map_midi_msgs<'a, T>(msgs: T) -> impl Iterator<Item = Event> + 'a
+    fn map_midi_msgs<'a, T>(
+        model: Model,
+        layout: Arc<Mutex<Layout>>,
+        orientation: Arc<Mutex<Orientation>>,
+        msgs: T,
+    ) -> impl Iterator<Item = (Duration, Event)> + 'a
     where
         T: Iterator<Item = midi::MidiMsg> + 'a,
     {
-        msgs.filter_map(|msg| match (msg.msg, (msg.param1 as u32).to_le_bytes()) {
-            (IN_DATA, [0x90, pos, 0x0, _]) => Some(Event::Up((pos & 0xF, pos / 16 + 1))),
-            (IN_DATA, [0x90, pos, 0x7F, _]) => Some(Event::Down((pos & 0xF, pos / 16 + 1))),
-            (IN_DATA, [0xB0, pos, 0x0, _]) => Some(Event::Up((pos & 0x7, 0))),
-            (IN_DATA, [0xB0, pos, 0x7F, _]) => Some(Event::Down((pos & 0x7, 0))),
-            _ => None,
+        msgs.filter_map(move |msg| {
+            let event = if model.is_rgb() {
+                Self::map_programmer_event(msg.event)?
+            } else {
+                Self::map_event(*layout.lock().unwrap(), msg.event)?
+            };
+            let orientation = *orientation.lock().unwrap();
+            let event = match event {
+                Event::Up(pad) => Event::Up(orientation.to_logical(pad)),
+                Event::Down(pad) => Event::Down(orientation.to_logical(pad)),
+                event => event,
+            };
+            Some((msg.timestamp, event))
         })
     }
+
+    fn map_event(layout: Layout, event: MidiEvent) -> Option<Event> {
+        match event {
+            MidiEvent::ControlChange {
+                channel: 0,
+                controller: 0x00,
+                value: 0x03,
+            } => Some(Event::TextScrolled),
+            MidiEvent::NoteOff {
+                channel: 0, note, ..
+            } => layout.note_pad(note).map(Event::Up),
+            MidiEvent::NoteOn {
+                channel: 0,
+                note,
+                velocity: 0x7F,
+            } => layout.note_pad(note).map(Event::Down),
+            MidiEvent::ControlChange {
+                channel: 0,
+                controller,
+                value: 0x0,
+            } => Layout::controller_pad(controller).map(Event::Up),
+            MidiEvent::ControlChange {
+                channel: 0,
+                controller,
+                value: 0x7F,
+            } => Layout::controller_pad(controller).map(Event::Down),
+            _ => None,
+        }
+    }
+
+    /// Pads send velocity sensitive notes, the buttons around them send CCs.
+    fn map_programmer_event(event: MidiEvent) -> Option<Event> {
+        match event {
+            MidiEvent::SysEx(data) => match data[..] {
+                [0xF0, 0x00, 0x20, 0x29, 0x02, _, 0x07, 0xF7] => Some(Event::TextScrolled),
+                _ => None,
+            },
+            MidiEvent::NoteOff {
+                channel: 0, note, ..
+            }
+            | MidiEvent::ControlChange {
+                channel: 0,
+                controller: note,
+                value: 0,
+            } => programmer_pad(note).map(Event::Up),
+            MidiEvent::NoteOn {
+                channel: 0, note, ..
+            }
+            | MidiEvent::ControlChange {
+                channel: 0,
+                controller: note,
+                ..
+            } => programmer_pad(note).map(Event::Down),
+            _ => None,
+        }
+    }
 }
 
 pub struct LaunchpadOut {
     out_dev: midi::OutDev,
+    model: Model,
+    layout: Arc<Mutex<Layout>>,
+    orientation: Arc<Mutex<Orientation>>,
+    buffered: bool,
+    displayed: u8,
+    auto_flash: bool,
+    brightness: u8,
 }
 
 impl LaunchpadOut {
     // TODO: Add more functions
     fn new(out_dev: midi::OutDev) -> Self {
-        Self { out_dev }
+        Self {
+            out_dev,
+            model: Model::Launchpad,
+            layout: Arc::new(Mutex::new(Layout::XY)),
+            orientation: Arc::new(Mutex::new(Orientation::default())),
+            buffered: false,
+            displayed: 0,
+            auto_flash: false,
+            brightness: 100,
+        }
+    }
+
+    /// Switches RGB models into programmer mode so the whole surface can be addressed.
+    fn set_model(&mut self, model: Model) -> LaunchpadResult<()> {
+        self.model = model;
+        if model.is_rgb() {
+            self.send_novation_sysex(&[0x0E, 0x01])?;
+        }
+        Ok(())
+    }
+
+    #[allow(dead_code)]
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    pub fn layout(&self) -> Layout {
+        *self.layout.lock().unwrap()
+    }
+
+    /// Selects the note layout of red/green models, the input side follows along.
+    #[allow(dead_code)]
+    pub fn set_layout(&mut self, layout: Layout) -> LaunchpadResult<()> {
+        if self.model.is_rgb() {
+            return Ok(());
+        }
+        self.out_dev.send(0xB0, 0x00, layout.control())?;
+        *self.layout.lock().unwrap() = layout;
+        Ok(())
+    }
+
+    pub fn orientation(&self) -> Orientation {
+        *self.orientation.lock().unwrap()
     }
 
+    /// Sets how the device is mounted, for both the LEDs and the input side.
+    ///
+    /// Pads take their new places with the next write, already lit LEDs stay where they are.
+    pub fn set_orientation(&mut self, orientation: Orientation) {
+        *self.orientation.lock().unwrap() = orientation;
+    }
+
+    #[allow(dead_code)]
     pub fn buf(self) -> LaunchpadOutBuf {
-        LaunchpadOutBuf::new(self)
+        let mut out_buf = LaunchpadOutBuf::new();
+        out_buf.brightness = self.brightness;
+        out_buf.out_pad = Some(self);
+        out_buf
     }
 
     pub fn clear(&mut self) -> LaunchpadResult<()> {
-        self.out_dev.send(0xb0, 0x0, 0x0).map_err(|err| err.into())
+        if self.model.is_rgb() {
+            return self.set_all(|_| PadColor::Palette(0));
+        }
+
+        // The reset also drops the buffer settings and the layout.
+        self.out_dev.send(0xb0, 0x0, 0x0)?;
+        if self.buffered || self.auto_flash {
+            self.send_buffer_control(false)?;
+        }
+        let layout = self.layout();
+        if layout != Layout::XY {
+            self.out_dev.send(0xB0, 0x00, layout.control())?;
+        }
+        if self.brightness != 100 {
+            self.send_brightness()?;
+        }
+        Ok(())
+    }
+
+    pub fn brightness(&self) -> u8 {
+        self.brightness
     }
 
-    //    pub fn fast(&self, col1: LaunchpadColor, col2: LaunchpadColor) -> MidiResult<()> {
-    //        self.out_dev.send(0x92, col1.into(), col2.into())
-    //    }
+    /// Dims all LEDs to `percent` of the default brightness, clamped to `0..=100`.
+    ///
+    /// Red/green models can't go fully dark, they use the closest LED duty cycle and stay
+    /// at the dimmest one for 0.
+    pub fn set_brightness(&mut self, percent: u8) -> LaunchpadResult<()> {
+        self.brightness = percent.min(100);
+        self.send_brightness()
+    }
+
+    fn send_brightness(&mut self) -> LaunchpadResult<()> {
+        if self.model.is_rgb() {
+            let level = (self.brightness as u16 * 127 / 100) as u8;
+            return self.send_novation_sysex(&[0x08, level]);
+        }
+
+        let (controller, data) = duty_cycle_control(duty_cycle(self.brightness));
+        Ok(self.out_dev.send(0xB0, controller, data)?)
+    }
 
-    pub fn set_color(&mut self, pos: (u8, u8), col: Color) -> LaunchpadResult<()> {
-        match pos {
-            (0..=7, 0) => self
-                .out_dev
-                .send(0xB0, pos.0 | 0x68, col.into())
-                .map_err(|err| err.into()),
-            (8, 0) => Ok(()),
-            (0..=8, 1..=8) => self
-                .out_dev
-                .send(0x90, (pos.1 - 1) * 16 + pos.0, col.into())
-                .map_err(|err| err.into()),
-            _ => Err(LaunchpadError::OutOfRange(pos.0, pos.1)),
+    /// While buffered, writes go to a hidden buffer and only show up after `swap_buffers`.
+    ///
+    /// Only the red/green models have a second buffer, RGB models keep writing directly.
+    /// Automatic flashing is paused while buffered.
+    #[allow(dead_code)]
+    pub fn set_buffered(&mut self, buffered: bool) -> LaunchpadResult<()> {
+        if self.model.is_rgb() || self.buffered == buffered {
+            return Ok(());
+        }
+        self.buffered = buffered;
+        // Start drawing from what is currently displayed.
+        self.send_buffer_control(true)
+    }
+
+    /// Shows the hidden buffer and continues drawing on a copy of it.
+    #[allow(dead_code)]
+    pub fn swap_buffers(&mut self) -> LaunchpadResult<()> {
+        if !self.buffered {
+            return Ok(());
         }
+        self.displayed ^= 1;
+        self.send_buffer_control(true)
+    }
+
+    /// Lets the hardware blink the LEDs set with `set_flashing`.
+    #[allow(dead_code)]
+    pub fn set_auto_flash(&mut self, auto_flash: bool) -> LaunchpadResult<()> {
+        if self.model.is_rgb() || self.auto_flash == auto_flash {
+            return Ok(());
+        }
+        self.auto_flash = auto_flash;
+        self.send_buffer_control(false)
+    }
+
+    /// Sends the CC 0 buffer control message for the current settings.
+    fn send_buffer_control(&mut self, copy: bool) -> LaunchpadResult<()> {
+        let (update, display, flash) = match self.buffered {
+            true => (self.displayed ^ 1, self.displayed, false),
+            false => (self.displayed, self.displayed, self.auto_flash),
+        };
+        let control = 0x20 | (copy as u8) << 4 | (flash as u8) << 3 | update << 2 | display;
+        Ok(self.out_dev.send(0xB0, 0x00, control)?)
+    }
+
+    /// Sets every LED at once, using rapid update on red/green models and a single
+    /// lighting SysEx on RGB models.
+    pub fn set_all<F>(&mut self, color_at: F) -> LaunchpadResult<()>
+    where
+        F: Fn(Pad) -> PadColor,
+    {
+        let orientation = self.orientation();
+        let color_at = |pad| color_at(orientation.to_logical(pad));
+        if self.model.is_rgb() {
+            let mut specs = vec![0x03];
+            for pad in Pad::all() {
+                specs.extend(programmer_spec(programmer_index(pad), color_at(pad)));
+            }
+            return self.send_novation_sysex(&specs);
+        }
+
+        // Any other message resets the rapid update cursor to the first pad.
+        self.send_buffer_control(false)?;
+        let flags = if self.buffered {
+            0
+        } else {
+            FLAG_CLEAR | FLAG_COPY
+        };
+        // Rapid update fills the LEDs in the order of `Pad::all`.
+        let velocities: Vec<u8> = Pad::all()
+            .map(|pad| u8::from(Color::from(color_at(pad))) | flags)
+            .collect();
+        for pair in velocities.chunks(2) {
+            self.out_dev.send(0x92, pair[0], pair[1])?;
+        }
+        Ok(())
+    }
+
+    pub fn set_color(&mut self, pad: Pad, col: impl Into<PadColor>) -> LaunchpadResult<()> {
+        let pad = self.orientation().to_physical(pad);
+        let col = col.into();
+        if self.model.is_rgb() {
+            return self.set_programmer_color(pad, col);
+        }
+
+        let flags = if self.buffered {
+            0
+        } else {
+            FLAG_CLEAR | FLAG_COPY
+        };
+        self.send_velocity(pad, u8::from(Color::from(col)) | flags)
+    }
+
+    /// Scrolls `text` across the grid, `Event::TextScrolled` is sent when it has passed.
+    ///
+    /// `speed` goes from 1 to 7. Looping text keeps scrolling until `stop_scrolling`
+    /// and reports every pass on red/green models only. Text other than printable ASCII
+    /// is shown as `?`. The device scrolls by itself and ignores the orientation.
+    #[allow(dead_code)]
+    pub fn scroll_text(
+        &mut self,
+        text: &str,
+        col: impl Into<PadColor>,
+        speed: u8,
+        looping: bool,
+    ) -> LaunchpadResult<()> {
+        let speed = speed.clamp(1, 7);
+        let text = text
+            .chars()
+            .map(|c| match c {
+                ' '..='~' => c as u8,
+                _ => b'?',
+            })
+            .collect::<Vec<_>>();
+
+        let col = col.into();
+        if self.model.is_rgb() {
+            let mut body = vec![0x07, looping as u8, speed * 3];
+            match col {
+                PadColor::Palette(palette) => body.extend_from_slice(&[0x00, palette & 0x7F]),
+                col => {
+                    let Rgb { r, g, b } = col.into();
+                    body.extend_from_slice(&[0x01, r >> 1, g >> 1, b >> 1]);
+                }
+            }
+            body.extend(text);
+            return self.send_novation_sysex(&body);
+        }
+
+        // The speed is set by a control byte in front of the text.
+        let mut data = vec![0xF0, 0x00, 0x20, 0x29, 0x09];
+        data.push(u8::from(Color::from(col)) | (looping as u8) << 6);
+        data.push(speed);
+        data.extend(text);
+        data.push(0xF7);
+        Ok(self.out_dev.send_sysex(&data)?)
+    }
+
+    #[allow(dead_code)]
+    pub fn stop_scrolling(&mut self) -> LaunchpadResult<()> {
+        if self.model.is_rgb() {
+            return self.send_novation_sysex(&[0x07]);
+        }
+        Ok(self
+            .out_dev
+            .send_sysex(&[0xF0, 0x00, 0x20, 0x29, 0x09, 0x00, 0xF7])?)
+    }
+
+    /// Makes the LED blink between `col` and off.
+    ///
+    /// Red/green models only blink while automatic flashing is enabled. RGB models blink
+    /// palette colors on their own and show other colors steadily.
+    #[allow(dead_code)]
+    pub fn set_flashing(&mut self, pad: Pad, col: impl Into<PadColor>) -> LaunchpadResult<()> {
+        let pad = self.orientation().to_physical(pad);
+        let col = col.into();
+        if self.model.is_rgb() {
+            return match col {
+                PadColor::Palette(palette) => {
+                    let status = match pad {
+                        Pad::Grid { .. } => 0x91,
+                        _ => 0xB1,
+                    };
+                    Ok(self
+                        .out_dev
+                        .send(status, programmer_index(pad), palette & 0x7F)?)
+                }
+                col => self.set_programmer_color(pad, col),
+            };
+        }
+
+        self.send_velocity(pad, u8::from(Color::from(col)) | FLAG_CLEAR)
+    }
+
+    fn send_velocity(&mut self, pad: Pad, velocity: u8) -> LaunchpadResult<()> {
+        let (status, data) = self.layout().message(pad);
+        Ok(self.out_dev.send(status, data, velocity)?)
+    }
+
+    fn set_programmer_color(&mut self, pad: Pad, col: PadColor) -> LaunchpadResult<()> {
+        let index = programmer_index(pad);
+        match col {
+            PadColor::Palette(palette) => {
+                let status = match pad {
+                    Pad::Grid { .. } => 0x90,
+                    _ => 0xB0,
+                };
+                Ok(self.out_dev.send(status, index, palette & 0x7F)?)
+            }
+            col => {
+                let mut specs = vec![0x03];
+                specs.extend(programmer_spec(index, col));
+                self.send_novation_sysex(&specs)
+            }
+        }
+    }
+
+    fn send_novation_sysex(&mut self, body: &[u8]) -> LaunchpadResult<()> {
+        let device = match self.model.sysex_device() {
+            Some(device) => device,
+            None => return Ok(()),
+        };
+        let mut data = vec![0xF0, 0x00, 0x20, 0x29, 0x02, device];
+        data.extend_from_slice(body);
+        data.push(0xF7);
+        Ok(self.out_dev.send_sysex(&data)?)
     }
 }
 
+impl Drop for LaunchpadOut {
+    fn drop(&mut self) {
+        // Hand the surface back to live mode, the device might already be gone.
+        if self.model.is_rgb() {
+            let _ = self.send_novation_sysex(&[0x0E, 0x00]);
+        }
+    }
+}
+
+/// The default LED duty cycle of red/green models, used as full brightness.
+const DEFAULT_DUTY_CYCLE: (u8, u8) = (1, 5);
+
+/// The duty cycle closest to `percent` of the default one.
+fn duty_cycle(percent: u8) -> (u8, u8) {
+    let (default_n, default_d) = DEFAULT_DUTY_CYCLE;
+    let target = default_n as f32 / default_d as f32 * percent as f32 / 100.0;
+    let error = |(n, d): (u8, u8)| (n as f32 / d as f32 - target).abs();
+    (1..=16)
+        .flat_map(|n| (3..=18).map(move |d| (n, d)))
+        .filter(|&(n, d)| n * default_d <= default_n * d)
+        .min_by(|&a, &b| error(a).partial_cmp(&error(b)).unwrap())
+        .unwrap()
+}
+
+/// The controller and value setting a duty cycle, numerators up to 8 and from 9 on have a
+/// controller each.
+fn duty_cycle_control((numerator, denominator): (u8, u8)) -> (u8, u8) {
+    if numerator < 9 {
+        (0x1E, (numerator - 1) * 16 + (denominator - 3))
+    } else {
+        (0x1F, (numerator - 9) * 16 + (denominator - 3))
+    }
+}
+
+/// Keeps the last written colors so they survive the device being unplugged.
+///
+/// While detached, writes only update the buffer and are sent once a device is attached again.
+/// A device that fails a write because it is gone is treated as unplugged and detached.
 pub struct LaunchpadOutBuf {
-    colors: Vec<u8>,
-    out_pad: LaunchpadOut,
+    colors: Vec<PadColor>,
+    brightness: u8,
+    out_pad: Option<LaunchpadOut>,
 }
 
 impl LaunchpadOutBuf {
-    fn new(out_pad: LaunchpadOut) -> Self {
+    pub fn new() -> Self {
         Self {
-            colors: vec![0; 81],
-            out_pad,
+            colors: vec![Color::BLACK.into(); Pad::COUNT],
+            brightness: 100,
+            out_pad: None,
+        }
+    }
+
+    pub fn attach(&mut self, out_pad: LaunchpadOut) -> LaunchpadResult<()> {
+        self.out_pad = Some(out_pad);
+        let brightness = self.brightness;
+        self.write(|out_pad| {
+            if out_pad.brightness() != brightness {
+                out_pad.set_brightness(brightness)?;
+            }
+            Ok(())
+        })?;
+        self.repaint()
+    }
+
+    pub fn detach(&mut self) -> Option<LaunchpadOut> {
+        self.out_pad.take()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_attached(&self) -> bool {
+        self.out_pad.is_some()
+    }
+
+    #[allow(dead_code)]
+    pub fn brightness(&self) -> u8 {
+        self.brightness
+    }
+
+    /// Like `LaunchpadOut::set_brightness`, kept for devices attached later.
+    pub fn set_brightness(&mut self, percent: u8) -> LaunchpadResult<()> {
+        self.brightness = percent.min(100);
+        let brightness = self.brightness;
+        self.write(|out_pad| out_pad.set_brightness(brightness))
+    }
+
+    /// Like `LaunchpadOut::set_orientation`, repainting the attached device.
+    #[allow(dead_code)]
+    pub fn set_orientation(&mut self, orientation: Orientation) -> LaunchpadResult<()> {
+        match self.out_pad.as_mut() {
+            Some(out_pad) => out_pad.set_orientation(orientation),
+            None => return Ok(()),
+        }
+        self.repaint()
+    }
+
+    pub fn repaint(&mut self) -> LaunchpadResult<()> {
+        self.write(|out_pad| out_pad.clear())?;
+        let lit = Pad::all()
+            .filter(|&pad| !self.get_color(pad).is_off())
+            .collect();
+        self.send_pads(lit)
+    }
+
+    /// Replaces all colors.
+    ///
+    /// Only changed pads are sent, all of them at once with rapid update when most changed.
+    pub fn flush_frame<F>(&mut self, color_at: F) -> LaunchpadResult<()>
+    where
+        F: Fn(Pad) -> PadColor,
+    {
+        let changed = Pad::all()
+            .filter(|&pad| color_at(pad) != self.get_color(pad))
+            .collect();
+        for pad in Pad::all() {
+            self.colors[pad.index()] = color_at(pad);
+        }
+        self.send_pads(changed)
+    }
+
+    fn send_pads(&mut self, pads: Vec<Pad>) -> LaunchpadResult<()> {
+        if pads.len() * 2 > Pad::COUNT {
+            let colors = self.colors.clone();
+            return self.write(|out_pad| out_pad.set_all(|pad| colors[pad.index()]));
+        }
+
+        for pad in pads {
+            let col = self.get_color(pad);
+            self.write(|out_pad| out_pad.set_color(pad, col))?;
         }
+        Ok(())
     }
 
+    #[allow(dead_code)]
     pub fn clear(&mut self) -> LaunchpadResult<()> {
-        self.out_pad.clear().map(|ret| {
-            self.colors.iter_mut().for_each(|x| *x = 0);
-            ret
-        })
+        self.write(|out_pad| out_pad.clear())?;
+        self.colors
+            .iter_mut()
+            .for_each(|x| *x = Color::BLACK.into());
+        Ok(())
     }
 
-    pub fn get_color(&self, pos: (u8, u8)) -> Color {
-        self.colors[pos.0 as usize + (pos.1 as usize * 9)].into()
+    pub fn get_color(&self, pad: Pad) -> PadColor {
+        self.colors[pad.index()]
     }
 
-    pub fn set_color(&mut self, pos: (u8, u8), col: Color) -> LaunchpadResult<()> {
-        self.out_pad.set_color(pos, col).map(|ret| {
-            self.colors[pos.0 as usize + (pos.1 as usize * 9)] = col.into();
-            ret
-        })
+    pub fn set_color(&mut self, pad: Pad, col: impl Into<PadColor>) -> LaunchpadResult<()> {
+        let col = col.into();
+        if self.get_color(pad) == col {
+            return Ok(());
+        }
+        self.write(|out_pad| out_pad.set_color(pad, col))?;
+        self.colors[pad.index()] = col;
+        Ok(())
+    }
+
+    /// Starts an off-screen copy of the current colors that is sent by `Frame::commit`.
+    pub fn frame(&mut self) -> Frame<'_> {
+        Frame {
+            colors: self.colors.clone(),
+            out_buf: self,
+        }
+    }
+
+    fn write<F>(&mut self, f: F) -> LaunchpadResult<()>
+    where
+        F: FnOnce(&mut LaunchpadOut) -> LaunchpadResult<()>,
+    {
+        match self.out_pad.as_mut().map(f) {
+            Some(Err(LaunchpadError::MidiError(err))) if err.is_disconnect() => {
+                self.out_pad = None;
+                Ok(())
+            }
+            Some(result) => result,
+            None => Ok(()),
+        }
     }
 }
 
-#[derive(Debug)]
+/// Colors drawn off-screen, only the cells that differ from the device are sent on commit.
+///
+/// Dropping a frame without committing discards it.
+pub struct Frame<'a> {
+    out_buf: &'a mut LaunchpadOutBuf,
+    colors: Vec<PadColor>,
+}
+
+impl Frame<'_> {
+    #[allow(dead_code)]
+    pub fn get_color(&self, pad: Pad) -> PadColor {
+        self.colors[pad.index()]
+    }
+
+    pub fn set_color(&mut self, pad: Pad, col: impl Into<PadColor>) {
+        self.colors[pad.index()] = col.into();
+    }
+
+    #[allow(dead_code)]
+    pub fn clear(&mut self) {
+        self.colors
+            .iter_mut()
+            .for_each(|x| *x = Color::BLACK.into());
+    }
+
+    pub fn commit(self) -> LaunchpadResult<()> {
+        let colors = self.colors;
+        self.out_buf.flush_frame(|pad| colors[pad.index()])
+    }
+}
+
+impl Default for LaunchpadOutBuf {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Debug, PartialEq, Eq)]
 pub enum Event {
-    Up((u8, u8)),
-    Down((u8, u8)),
+    Up(Pad),
+    Down(Pad),
+    /// Scrolling text has passed the grid.
+    TextScrolled,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -194,3 +1021,447 @@ impl From<Color> for u8 {
         col.0
     }
 }
+
+impl From<PadColor> for Color {
+    /// Picks the closest of the 16 red/green colors.
+    fn from(col: PadColor) -> Self {
+        match col {
+            PadColor::RedGreen(col) => col,
+            col => {
+                let Rgb { r, g, .. } = col.into();
+                let level = |val: u8| ((val as u16 * 3 + 127) / 255) as u8;
+                Self::from((level(r), level(g)))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+}
+
+/// A color for any model, converted to what the hardware can display when it's sent.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PadColor {
+    RedGreen(Color),
+    /// An index into the built in palette of the RGB models.
+    Palette(u8),
+    Rgb(Rgb),
+}
+
+impl PadColor {
+    pub fn is_off(self) -> bool {
+        Rgb::from(self) == Rgb::new(0, 0, 0)
+    }
+}
+
+impl From<Color> for PadColor {
+    fn from(col: Color) -> Self {
+        PadColor::RedGreen(col)
+    }
+}
+
+impl From<Rgb> for PadColor {
+    fn from(col: Rgb) -> Self {
+        PadColor::Rgb(col)
+    }
+}
+
+impl From<PadColor> for Rgb {
+    fn from(col: PadColor) -> Self {
+        match col {
+            PadColor::RedGreen(col) => {
+                let col = u8::from(col);
+                Rgb::new((col & 0x3) * 85, ((col >> 4) & 0x3) * 85, 0)
+            }
+            PadColor::Palette(index) => {
+                let [_, r, g, b] = PALETTE[(index & 0x7F) as usize].to_be_bytes();
+                Rgb::new(r, g, b)
+            }
+            PadColor::Rgb(col) => col,
+        }
+    }
+}
+
+/// The default palette of the RGB models as 0xRRGGBB.
+#[rustfmt::skip]
+const PALETTE: [u32; 128] = [
+    0x000000, 0x1C1C1C, 0x7C7C7C, 0xFCFCFC, 0xFF4C4C, 0xFF0000, 0x590000, 0x190000,
+    0xFFBD6C, 0xFF5400, 0x591D00, 0x271B00, 0xFFFF4C, 0xFFFF00, 0x595900, 0x191900,
+    0x88FF4C, 0x54FF00, 0x1D5900, 0x142B00, 0x4CFF4C, 0x00FF00, 0x005900, 0x001900,
+    0x4CFF5E, 0x00FF19, 0x00590D, 0x001902, 0x4CFF88, 0x00FF55, 0x00591D, 0x001F12,
+    0x4CFFB7, 0x00FF99, 0x005935, 0x001912, 0x4CC3FF, 0x00A9FF, 0x004152, 0x001019,
+    0x4C88FF, 0x0055FF, 0x001D59, 0x000819, 0x4C4CFF, 0x0000FF, 0x000059, 0x000019,
+    0x874CFF, 0x5400FF, 0x190064, 0x0F0030, 0xFF4CFF, 0xFF00FF, 0x590059, 0x190019,
+    0xFF4C87, 0xFF0054, 0x59001D, 0x220013, 0xFF1500, 0x993500, 0x795100, 0x436400,
+    0x033900, 0x005735, 0x00547F, 0x0000FF, 0x00454F, 0x2500CC, 0x7F7F7F, 0x202020,
+    0xFF0000, 0xBDFF2D, 0xAFED06, 0x64FF09, 0x108B00, 0x00FF87, 0x00A9FF, 0x002AFF,
+    0x3F00FF, 0x7A00FF, 0xB21A7D, 0x402100, 0xFF4A00, 0x88E106, 0x72FF15, 0x00FF00,
+    0x3BFF26, 0x59FF71, 0x38FFCC, 0x5B8AFF, 0x3151C6, 0x877FE9, 0xD31DFF, 0xFF005D,
+    0xFF7F00, 0xB9B000, 0x90FF00, 0x835D07, 0x392B00, 0x144C10, 0x0D5038, 0x15152A,
+    0x16205A, 0x693C1C, 0xA8000A, 0xDE513D, 0xD86A1C, 0xFFE126, 0x9EE12F, 0x67B50F,
+    0x1E1E30, 0xDCFF6B, 0x80FFBD, 0x9A99FF, 0x8E66FF, 0x404040, 0x757575, 0xE0FFFF,
+    0xA00000, 0x350000, 0x1AD000, 0x074200, 0xB9B000, 0x3F3100, 0xB35F00, 0x4B1502,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_midi::{self, MockHandle};
+    use crate::pad::Rotation;
+
+    const RECV_TIMEOUT: Duration = Duration::from_secs(1);
+
+    /// Plugs in a Novation device reporting `family`.
+    fn plug(name: &str, family: u16) -> MockHandle {
+        let device = mock_midi::add_device(name);
+        let [family_lsb, family_msb] = family.to_le_bytes();
+        device.set_identity(&[
+            0xF0, 0x7E, 0x00, 0x06, 0x02, 0x00, 0x20, 0x29, family_lsb, family_msb, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x01, 0xF7,
+        ]);
+        device
+    }
+
+    /// Plugs in a Novation device reporting `family` and opens it.
+    fn open(name: &str, family: u16) -> (MockHandle, LaunchpadIn, LaunchpadOut) {
+        let device = plug(name, family);
+        let uninit_pad = find_launchpad(name).unwrap();
+        let (in_pad, out_pad) = uninit_pad.init().unwrap();
+        device.take_sent();
+        device.take_sent_sysex();
+        (device, in_pad, out_pad)
+    }
+
+    fn pad(x: u8, y: u8) -> Pad {
+        Pad::grid(x, y).unwrap()
+    }
+
+    #[test]
+    fn enumerate_launchpads_pairs_ports_of_this_registry() {
+        mock_midi::add_device("Launchpad Mini");
+        mock_midi::add_device("Launchpad Mini");
+        mock_midi::add_device("Some Synth");
+        mock_midi::add_device("Launchpad X LPX DAW Out");
+
+        let ids: Vec<String> = enumerate_launchpads()
+            .map(|pad| pad.id().to_owned())
+            .collect();
+        assert_eq!(ids, vec!["Launchpad Mini", "Launchpad Mini#2"]);
+
+        let other = std::thread::spawn(|| enumerate_launchpads().count());
+        assert_eq!(other.join().unwrap(), 0);
+
+        let registry = mock_midi::Registry::current();
+        let shared = std::thread::spawn(move || {
+            registry.enter();
+            enumerate_launchpads().count()
+        });
+        assert_eq!(shared.join().unwrap(), 2);
+    }
+
+    #[test]
+    fn unplugging_keeps_the_ids_of_other_devices() {
+        let first = mock_midi::add_device_at("Launchpad Mini", "usb-1");
+        mock_midi::add_device_at("Launchpad Mini", "usb-2");
+        let ids = || -> Vec<String> {
+            enumerate_launchpads()
+                .map(|pad| pad.id().to_owned())
+                .collect()
+        };
+        let both = ids();
+        assert_eq!(
+            both,
+            vec!["Launchpad Mini (usb-1)", "Launchpad Mini (usb-2)"]
+        );
+
+        first.remove();
+        assert_eq!(ids(), vec!["Launchpad Mini (usb-2)"]);
+    }
+
+    #[test]
+    fn init_detects_model() {
+        let (device, in_pad, out_pad) = open("Launchpad S", 0x0020);
+        assert_eq!(in_pad.model(), Model::LaunchpadS);
+        assert_eq!(out_pad.model(), Model::LaunchpadS);
+        drop(out_pad);
+        // Red/green models have no live mode to return to.
+        assert_eq!(device.sent_sysex(), Vec::<Vec<u8>>::new());
+    }
+
+    #[test]
+    fn init_rejects_unsupported_models() {
+        for (name, family, model) in [
+            ("Launchpad MK2", 0x0069, Model::LaunchpadMk2),
+            ("Launchpad Pro", 0x0051, Model::LaunchpadPro),
+        ] {
+            let device = plug(name, family);
+            let uninit_pad = find_launchpad(name).unwrap();
+            match uninit_pad.init() {
+                Err(LaunchpadError::UnsupportedModel(unsupported)) => {
+                    assert_eq!(unsupported, model)
+                }
+                Err(err) => panic!("unexpected error {}", err),
+                Ok(_) => panic!("{:?} was opened", model),
+            }
+            assert_eq!(uninit_pad.model(), Some(model));
+            assert_eq!(device.sent(), Vec::<[u8; 3]>::new());
+        }
+    }
+
+    #[test]
+    fn set_color_red_green() {
+        let (device, _in_pad, mut out_pad) = open("Launchpad S", 0x0020);
+        out_pad.set_color(pad(1, 2), Color::RED).unwrap();
+        out_pad.set_color(Pad::RIGHT, Color::GREEN).unwrap();
+        out_pad.set_color(Pad::VOL, Rgb::new(255, 0, 0)).unwrap();
+        let sent = vec![[0x90, 0x21, 0x0F], [0xB0, 0x6B, 0x3C], [0x90, 0x08, 0x0F]];
+        assert_eq!(device.take_sent(), sent);
+    }
+
+    #[test]
+    fn set_color_rgb() {
+        let (device, _in_pad, mut out_pad) = open("LPX MIDI", 0x0103);
+        out_pad.set_color(pad(0, 7), PadColor::Palette(5)).unwrap();
+        out_pad.set_color(Pad::UP, PadColor::Palette(5)).unwrap();
+        assert_eq!(device.take_sent(), vec![[0x90, 11, 5], [0xB0, 91, 5]]);
+
+        out_pad.set_color(pad(7, 0), Rgb::new(255, 128, 2)).unwrap();
+        let sysex = vec![
+            0xF0, 0x00, 0x20, 0x29, 0x02, 0x0C, 0x03, 0x03, 88, 127, 64, 1, 0xF7,
+        ];
+        assert_eq!(device.take_sent_sysex(), vec![sysex]);
+    }
+
+    #[test]
+    fn duty_cycle_scales_the_default() {
+        assert_eq!(duty_cycle(100), (1, 5));
+        assert_eq!(duty_cycle(0), (1, 18));
+        assert_eq!(duty_cycle(50), (1, 10));
+        assert_eq!(duty_cycle(200), (1, 5));
+    }
+
+    #[test]
+    fn duty_cycle_control_bytes() {
+        assert_eq!(duty_cycle_control((1, 3)), (0x1E, 0x00));
+        assert_eq!(duty_cycle_control((1, 5)), (0x1E, 0x02));
+        assert_eq!(duty_cycle_control((8, 18)), (0x1E, 0x7F));
+        assert_eq!(duty_cycle_control((9, 3)), (0x1F, 0x00));
+        assert_eq!(duty_cycle_control((16, 18)), (0x1F, 0x7F));
+    }
+
+    #[test]
+    fn set_brightness_red_green() {
+        let (device, _in_pad, mut out_pad) = open("Launchpad S", 0x0020);
+        for &percent in [100, 50, 0].iter() {
+            out_pad.set_brightness(percent).unwrap();
+        }
+        let sent = vec![[0xB0, 0x1E, 0x02], [0xB0, 0x1E, 0x07], [0xB0, 0x1E, 0x0F]];
+        assert_eq!(device.take_sent(), sent);
+    }
+
+    #[test]
+    fn set_flashing_rgb() {
+        let (device, _in_pad, mut out_pad) = open("LPX MIDI", 0x0103);
+        out_pad
+            .set_flashing(pad(0, 7), PadColor::Palette(5))
+            .unwrap();
+        out_pad.set_flashing(Pad::UP, PadColor::Palette(5)).unwrap();
+        out_pad
+            .set_flashing(Pad::side(0).unwrap(), PadColor::Palette(5))
+            .unwrap();
+        let sent = vec![[0x91, 11, 5], [0xB1, 91, 5], [0xB1, 89, 5]];
+        assert_eq!(device.take_sent(), sent);
+    }
+
+    #[test]
+    fn map_midi_msgs_red_green() {
+        let (device, mut in_pad, _out_pad) = open("Launchpad S", 0x0020);
+        let ts = Duration::from_millis(5);
+        assert!(device.inject_short([0x90, 0x21, 0x7F], ts));
+        assert!(device.inject_short([0x90, 0x21, 0x00], ts));
+        assert!(device.inject_short([0xB0, 0x68, 0x7F], ts));
+        // Other channels and velocities aren't pads.
+        assert!(device.inject_short([0x91, 0x21, 0x7F], ts));
+        assert!(device.inject_short([0x90, 0x21, 0x40], ts));
+        assert!(device.inject_short([0xB0, 0x00, 0x03], ts));
+
+        let first = in_pad.recv_timed_timeout(RECV_TIMEOUT).unwrap();
+        assert_eq!(first, (ts, Event::Down(pad(1, 2))));
+        let events: Vec<Event> = in_pad.current_msgs().collect();
+        let expected = vec![
+            Event::Up(pad(1, 2)),
+            Event::Down(Pad::UP),
+            Event::TextScrolled,
+        ];
+        assert_eq!(events, expected);
+    }
+
+    #[test]
+    fn map_midi_msgs_rgb() {
+        let (device, mut in_pad, _out_pad) = open("LPX MIDI", 0x0103);
+        let ts = Duration::from_millis(5);
+        assert!(device.inject_short([0x90, 11, 0x40], ts));
+        assert!(device.inject_short([0x90, 11, 0x00], ts));
+        assert!(device.inject_short([0xB0, 19, 0x7F], ts));
+        assert!(device.inject_short([0xB0, 98, 0x00], ts));
+
+        let events: Vec<Event> = in_pad.current_msgs().collect();
+        let expected = vec![
+            Event::Down(pad(0, 7)),
+            Event::Up(pad(0, 7)),
+            Event::Down(Pad::side(7).unwrap()),
+            Event::Up(Pad::top(7).unwrap()),
+        ];
+        assert_eq!(events, expected);
+    }
+
+    #[test]
+    fn set_all_rapid_update() {
+        let (device, _in_pad, mut out_pad) = open("Launchpad S", 0x0020);
+        out_pad
+            .set_all(|p| match p {
+                Pad::Grid { x, y } if x.get() == 0 && y.get() == 0 => Color::GREEN.into(),
+                Pad::Grid { .. } => Color::RED.into(),
+                Pad::Side(_) => Color::GREEN.into(),
+                Pad::Top(_) => Color::YELLOW.into(),
+            })
+            .unwrap();
+
+        let sent = device.take_sent();
+        // The buffer control message resets the cursor to the top left pad.
+        assert_eq!(sent[0], [0xB0, 0x00, 0x20]);
+        let rapid = &sent[1..];
+        assert_eq!(rapid.len(), 40);
+        let (red, green, yellow) = (0x0F, 0x3C, 0x3D);
+        assert_eq!(rapid[0], [0x92, green, red]);
+        assert!(rapid[1..32].iter().all(|&msg| msg == [0x92, red, red]));
+        assert!(rapid[32..36].iter().all(|&msg| msg == [0x92, green, green]));
+        assert!(rapid[36..].iter().all(|&msg| msg == [0x92, yellow, yellow]));
+    }
+
+    #[test]
+    fn frames_use_rapid_update_when_most_pads_changed() {
+        let (device, _in_pad, out_pad) = open("Launchpad S", 0x0020);
+        let mut out_buf = out_pad.buf();
+
+        // Half of the pads are sent one by one.
+        let mut frame = out_buf.frame();
+        for p in Pad::all().take(Pad::COUNT / 2) {
+            frame.set_color(p, Color::RED);
+        }
+        frame.commit().unwrap();
+        let sent = device.take_sent();
+        assert_eq!(sent.len(), Pad::COUNT / 2);
+        assert_eq!(sent[0], [0x90, 0x00, 0x0F]);
+        assert!(sent
+            .iter()
+            .all(|&[status, _, col]| status == 0x90 && col == 0x0F));
+
+        // More than half are sent all at once.
+        let mut frame = out_buf.frame();
+        for p in Pad::all().take(Pad::COUNT / 2 + 1) {
+            frame.set_color(p, Color::GREEN);
+        }
+        frame.commit().unwrap();
+        let sent = device.take_sent();
+        assert_eq!(sent.len(), 1 + Pad::COUNT / 2);
+        assert_eq!(sent[0], [0xB0, 0x00, 0x20]);
+        let (green, black) = (0x3C, 0x0C);
+        assert_eq!(sent[1], [0x92, green, green]);
+        assert_eq!(sent[21], [0x92, green, black]);
+        assert_eq!(sent[22], [0x92, black, black]);
+
+        // A small change afterwards goes back to single pads.
+        let mut frame = out_buf.frame();
+        frame.set_color(pad(1, 2), Color::RED);
+        frame.commit().unwrap();
+        assert_eq!(device.take_sent(), vec![[0x90, 0x21, 0x0F]]);
+    }
+
+    #[test]
+    fn drum_rack_layout_round_trips_through_input() {
+        let (device, mut in_pad, mut out_pad) = open("Launchpad S", 0x0020);
+        out_pad.set_layout(Layout::DrumRack).unwrap();
+        assert_eq!(device.take_sent(), vec![[0xB0, 0x00, 0x02]]);
+
+        for p in Pad::all() {
+            out_pad.set_color(p, Color::RED).unwrap();
+            let (status, data) = match device.take_sent()[..] {
+                [[status, data, _]] => (status, data),
+                ref sent => panic!("{:?} was sent as {:?}", p, sent),
+            };
+            assert!(device.inject_short([status, data, 0x7F], Duration::from_secs(0)));
+            assert_eq!(in_pad.recv_timeout(RECV_TIMEOUT).unwrap(), Event::Down(p));
+        }
+        let sent = [pad(0, 7), pad(4, 0), Pad::side(0).unwrap()].map(|p| {
+            out_pad.set_color(p, Color::RED).unwrap();
+            device.take_sent()[0][1]
+        });
+        assert_eq!(sent, [36, 96, 100]);
+
+        // The reset drops the layout, so it is sent again.
+        out_pad.clear().unwrap();
+        let sent = vec![[0xB0, 0x00, 0x00], [0xB0, 0x00, 0x02]];
+        assert_eq!(device.take_sent(), sent);
+    }
+
+    #[test]
+    fn frames_send_only_changed_pads() {
+        let (device, _in_pad, out_pad) = open("Launchpad S", 0x0020);
+        let mut out_buf = out_pad.buf();
+        out_buf.set_color(pad(0, 0), Color::RED).unwrap();
+        device.take_sent();
+
+        let mut frame = out_buf.frame();
+        frame.set_color(pad(0, 0), Color::RED);
+        frame.set_color(pad(1, 2), Color::GREEN);
+        frame.commit().unwrap();
+        assert_eq!(device.take_sent(), vec![[0x90, 0x21, 0x3C]]);
+
+        out_buf.set_color(pad(1, 2), Color::GREEN).unwrap();
+        assert_eq!(device.take_sent(), Vec::<[u8; 3]>::new());
+        assert_eq!(out_buf.get_color(pad(1, 2)), Color::GREEN.into());
+    }
+
+    /// Pressing the pad a color was sent to reports the same pad, however the device is turned.
+    #[test]
+    fn set_color_round_trips_through_input() {
+        for &name in ["Launchpad S", "LPX MIDI"].iter() {
+            let family = if name == "LPX MIDI" { 0x0103 } else { 0x0020 };
+            let (device, mut in_pad, mut out_pad) = open(name, family);
+            for &rotation in [
+                Rotation::None,
+                Rotation::Cw90,
+                Rotation::Cw180,
+                Rotation::Cw270,
+            ]
+            .iter()
+            {
+                for &mirrored in [false, true].iter() {
+                    out_pad.set_orientation(Orientation { rotation, mirrored });
+                    for pad in Pad::all() {
+                        out_pad.set_color(pad, PadColor::Palette(5)).unwrap();
+                        let (status, data) = match device.take_sent()[..] {
+                            [[status, data, _]] => (status, data),
+                            ref sent => panic!("{:?} was sent as {:?}", pad, sent),
+                        };
+                        assert!(device.inject_short([status, data, 0x7F], Duration::from_secs(0)));
+                        let event = in_pad.recv_timeout(RECV_TIMEOUT).unwrap();
+                        assert_eq!(event, Event::Down(pad), "{} {:?}", name, rotation);
+                    }
+                }
+            }
+        }
+    }
+}
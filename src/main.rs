@@ -1,18 +1,44 @@
+#[cfg(all(target_os = "linux", not(test)))]
+mod alsa_midi;
+mod canvas;
+mod font;
+mod gesture;
+mod hotplug;
 mod launchpad;
+mod midi;
+#[cfg(test)]
+mod mock_midi;
+mod pad;
+mod protocol;
+#[cfg(all(windows, not(test)))]
 mod win_midi;
+#[cfg(all(windows, not(test)))]
 mod win_midi_sys;
 
-use crate::launchpad::{Color, Event, LaunchpadIn, LaunchpadOutBuf};
-use std::net::{TcpListener, TcpStream};
-use std::sync::{mpsc, Arc, Mutex};
+use crate::canvas::{Canvas, CanvasFrame, CanvasPad, Placement};
+use crate::gesture::{Gesture, GestureConfig, Gestures};
+use crate::hotplug::DeviceEvent;
+use crate::launchpad::{Color, LaunchpadIn, LaunchpadResult, PadColor, Rgb, UninitLaunchpad};
+use crate::pad::{Orientation, Pad, Rotation};
+use crate::protocol::{ClientMsg, Mode, ServerMsg, Status};
+use futures_util::{SinkExt, StreamExt};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, Mutex};
 use std::thread::spawn;
-use tungstenite::error::Error as WsError;
-use tungstenite::{accept, Message};
+use std::time::{Duration, Instant};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
 
 trait OptVec<T> {
+    #[allow(dead_code)]
     fn empty_index(&self) -> usize;
     fn get_inner(&self, index: usize) -> Option<&T>;
+    #[allow(dead_code)]
     fn push_empty(&mut self, item: T) -> usize;
+    fn put_at(&mut self, index: usize, item: T);
     fn take_at(&mut self, index: usize) -> Option<T>;
 }
 
@@ -20,7 +46,7 @@ impl<T> OptVec<T> for Vec<Option<T>> {
     fn empty_index(&self) -> usize {
         self.iter()
             .position(|opt| opt.is_none())
-            .unwrap_or_else(|| self.len())
+            .unwrap_or(self.len())
     }
 
     fn get_inner(&self, index: usize) -> Option<&T> {
@@ -37,133 +63,810 @@ impl<T> OptVec<T> for Vec<Option<T>> {
         }
     }
 
+    fn put_at(&mut self, index: usize, item: T) {
+        if self.len() <= index {
+            self.resize_with(index + 1, || None);
+        }
+        self[index] = Some(item);
+    }
+
     fn take_at(&mut self, index: usize) -> Option<T> {
         self.get_mut(index)?.take()
     }
 }
 
+struct Client {
+    tx: mpsc::UnboundedSender<ServerMsg>,
+    /// The color of the client's pad while selected.
+    color: PadColor,
+}
+
 struct State {
-    current: Option<u8>,
-    out_pad: LaunchpadOutBuf,
-    out_vec: Vec<Option<mpsc::Sender<String>>>,
+    current: Option<usize>,
+    /// The page of clients shown on the grid.
+    page: usize,
+    out_pad: Canvas,
+    out_vec: Vec<Option<Client>>,
+    /// The slots of client ids, configured or given to an id on its first hello.
+    reserved: HashMap<String, usize>,
+    /// Clients whose connection dropped by their resume token, kept until their grace period
+    /// ends.
+    detached: HashMap<String, Detached>,
+}
+
+/// The session of a dropped connection, its events queue up in `rx`.
+struct Detached {
+    index: usize,
+    rx: mpsc::UnboundedReceiver<ServerMsg>,
+    until: Instant,
 }
 
 impl State {
-    fn new(out_pad: LaunchpadOutBuf) -> Self {
+    fn new(out_pad: Canvas, reserved: HashMap<String, usize>) -> Self {
         Self {
             current: None,
+            page: 0,
             out_pad,
             out_vec: Vec::new(),
+            reserved,
+            detached: HashMap::new(),
+        }
+    }
+
+    /// The first free slot no client id is waiting for.
+    fn free_slot(&self) -> usize {
+        (0..)
+            .filter(|&index| self.out_vec.get_inner(index).is_none())
+            .find(|index| !self.reserved.values().any(|slot| slot == index))
+            .unwrap()
+    }
+
+    /// The number of pages up to the last client or the shown page.
+    fn pages(&self, slots: &Slots) -> usize {
+        let last = self.out_vec.iter().rposition(Option::is_some);
+        let pages = last.map_or(1, |last| slots.page(last) + 1);
+        pages.max(self.page + 1)
+    }
+}
+
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+const PAD_POLL_INTERVAL: Duration = Duration::from_millis(100);
+const DEFAULT_GRACE: Duration = Duration::from_secs(30);
+/// The legend buttons right of the selected client's color send their number as an answer.
+const LEGEND_ANSWERS: usize = 4;
+
+/// How the client pads and the legend are laid out on the cells the devices cover.
+///
+/// The bottom row holds the legend, clients fill the other cells row by row and page by
+/// page. The arrow buttons turn the pages.
+#[derive(Clone)]
+struct Slots {
+    /// The cells of one page of clients.
+    cells: Vec<(u16, u16)>,
+    /// The status of the selected client, the answers it can be sent and the page indicator,
+    /// from the left.
+    legend: Vec<(u16, u16)>,
+}
+
+impl Slots {
+    fn new(canvas: &Canvas) -> Self {
+        let legend_row = canvas.height() - 1;
+        let (legend, cells) = (0..canvas.height())
+            .flat_map(|y| (0..canvas.width()).map(move |x| (x, y)))
+            .filter(|&(x, y)| canvas.covers(x, y))
+            .partition(|&(_, y)| y == legend_row);
+        Self { cells, legend }
+    }
+
+    fn per_page(&self) -> usize {
+        self.cells.len()
+    }
+
+    fn page(&self, index: usize) -> usize {
+        index / self.per_page()
+    }
+
+    /// The pad of the client in slot `index` while `page` is shown, if it is on that page.
+    fn pad(&self, page: usize, index: usize) -> Option<CanvasPad> {
+        if self.page(index) != page {
+            return None;
         }
+        let (x, y) = self.cells[index % self.per_page()];
+        Some(CanvasPad::Grid { x, y })
+    }
+
+    /// The slot shown at `x`, `y` while `page` is shown, `None` off the client cells.
+    fn index(&self, page: usize, x: u16, y: u16) -> Option<usize> {
+        let cell = self.cells.iter().position(|&cell| cell == (x, y))?;
+        Some(page * self.per_page() + cell)
+    }
+
+    fn legend(&self, pos: usize) -> CanvasPad {
+        let (x, y) = self.legend[pos];
+        CanvasPad::Grid { x, y }
+    }
+
+    /// The position of `x`, `y` on the legend, if it is part of it.
+    fn legend_position(&self, x: u16, y: u16) -> Option<usize> {
+        self.legend.iter().position(|&cell| cell == (x, y))
     }
 }
 
-fn main() -> Result<(), anyhow::Error> {
-    if let Some(uninit_pad) = launchpad::enumerate_launchpads().next() {
-        let (in_pad, out_pad) = uninit_pad.init()?;
-        let mut out_pad = out_pad.buf();
-        out_pad.clear()?;
-        out_pad.set_color((1, 8), Color::YELLOW)?;
-        out_pad.set_color((2, 8), Color::ORANGE)?;
-        out_pad.set_color((3, 8), Color::RED)?;
-        out_pad.set_color((4, 8), Color::GREEN)?;
+#[tokio::main]
+async fn main() -> Result<(), anyhow::Error> {
+    let mut devices: Vec<(Option<String>, Placement)> = Vec::new();
+    let mut brightness = 100;
+    let mut clients = Vec::new();
+    let mut grace = DEFAULT_GRACE;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--list" => {
+                for uninit_pad in launchpad::enumerate_launchpads() {
+                    println!("{}\t{}", uninit_pad.id(), uninit_pad.name());
+                }
+                return Ok(());
+            }
+            "--device" => match args.next() {
+                Some(arg) => {
+                    let previous = devices.last().map(|(_, placement)| *placement);
+                    devices.push(parse_device(&arg, previous)?);
+                }
+                None => anyhow::bail!("--device requires a device id, see --list"),
+            },
+            "--brightness" => match args.next().and_then(|level| level.parse().ok()) {
+                Some(level) if level <= 100 => brightness = level,
+                _ => anyhow::bail!("--brightness requires a percentage from 0 to 100"),
+            },
+            "--client" => match args.next().as_deref().and_then(parse_client) {
+                Some(client) => clients.push(client),
+                None => anyhow::bail!("--client requires a client id and its pad, <id>=<x>,<y>"),
+            },
+            "--grace" => match args.next().and_then(|secs| secs.parse().ok()) {
+                Some(secs) => grace = Duration::from_secs(secs),
+                None => anyhow::bail!("--grace requires the seconds to keep a dropped client"),
+            },
+            _ => anyhow::bail!(
+                "Unknown argument {}, expected --list, --device <id>[@x,y[,degrees[,mirror]]], \
+                 --brightness <percent>, --client <id>=<x>,<y> or --grace <seconds>",
+                arg
+            ),
+        }
+    }
+
+    if devices.is_empty() {
+        devices.push((None, Placement::default()));
+    }
+
+    let mut out_pad = Canvas::new();
+    for (_, placement) in devices.iter() {
+        out_pad.add_device(*placement);
+    }
+    let slots = Arc::new(Slots::new(&out_pad));
+    // Dims the LEDs, e.g. for night use.
+    out_pad.set_brightness(brightness)?;
+    out_pad.set_color(slots.legend(1), Color::YELLOW)?;
+    out_pad.set_color(slots.legend(2), Color::ORANGE)?;
+    out_pad.set_color(slots.legend(3), Color::RED)?;
+    out_pad.set_color(slots.legend(4), Color::GREEN)?;
+    let mut frame = out_pad.frame();
+    paint_pages(&mut frame, &slots, 0, 1);
+    frame.commit()?;
+
+    let mut reserved = HashMap::new();
+    for (id, x, y) in clients {
+        match slots.index(0, x, y) {
+            Some(index) => reserved.insert(id, index),
+            None => anyhow::bail!(
+                "The pad {},{} of client {} is not on a device or is part of the legend",
+                x,
+                y,
+                id
+            ),
+        };
+    }
+
+    let state = Arc::new(Mutex::new(State::new(out_pad, reserved)));
 
-        let state = Arc::new(Mutex::new(State::new(out_pad)));
+    let state_c = state.clone();
+    let ids = devices.into_iter().map(|(id, _)| id).collect();
+    let slots_c = slots.clone();
+    spawn(move || device_thread(state_c, ids, slots_c));
 
+    let server = TcpListener::bind("localhost:3012").await?;
+    loop {
+        let (stream, _) = server.accept().await?;
         let state_c = state.clone();
-        spawn(move || pad_thread(in_pad, state_c));
+        tokio::spawn(serve_client(stream, state_c, slots.clone(), grace));
+    }
+}
 
-        let server = TcpListener::bind("localhost:3012")?;
-        for stream in server.incoming() {
-            let stream = stream?;
-            stream.set_read_timeout(Some(std::time::Duration::from_millis(100)))?;
+/// Parses `<id>[@x,y[,degrees[,mirror]]]`, `*` standing for any Launchpad.
+///
+/// Without a position the device goes right of the `previous` one.
+fn parse_device(
+    arg: &str,
+    previous: Option<Placement>,
+) -> Result<(Option<String>, Placement), anyhow::Error> {
+    let (id, placement) = match arg.rsplit_once('@') {
+        Some((id, position)) => match parse_placement(position) {
+            Some(placement) => (id, placement),
+            None => anyhow::bail!(
+                "Invalid placement {}, expected x,y[,degrees[,mirror]] with x and y up to \
+                 {} and degrees 0, 90, 180 or 270",
+                position,
+                u16::MAX - 8
+            ),
+        },
+        None => {
+            let placement = match previous {
+                Some(previous) => match fits(previous.x.checked_add(8)) {
+                    Some(x) => Placement { x, ..previous },
+                    None => anyhow::bail!("There is no room right of the previous device"),
+                },
+                None => Placement::default(),
+            };
+            (arg, placement)
+        }
+    };
+    let id = Some(id.to_owned()).filter(|id| id != "*");
+    Ok((id, placement))
+}
+
+/// Parses `<id>=<x>,<y>`, the pad a client always gets.
+fn parse_client(arg: &str) -> Option<(String, u16, u16)> {
+    let (id, pad) = arg.rsplit_once('=')?;
+    let (x, y) = pad.split_once(',')?;
+    Some((
+        id.to_owned(),
+        x.trim().parse().ok()?,
+        y.trim().parse().ok()?,
+    ))
+}
+
+fn parse_placement(position: &str) -> Option<Placement> {
+    let fields: Vec<&str> = position.split(',').map(str::trim).collect();
+    let (x, y, degrees, mirror) = match fields[..] {
+        [x, y] => (x, y, "0", None),
+        [x, y, degrees] => (x, y, degrees, None),
+        [x, y, degrees, mirror] => (x, y, degrees, Some(mirror)),
+        _ => return None,
+    };
+    let orientation = Orientation {
+        rotation: Rotation::from_degrees(degrees.parse().ok()?)?,
+        mirrored: match mirror {
+            None => false,
+            Some("mirror") => true,
+            Some(_) => return None,
+        },
+    };
+    Some(Placement {
+        x: fits(x.parse().ok())?,
+        y: fits(y.parse().ok())?,
+        orientation,
+    })
+}
+
+/// Keeps a position whose device ends within the coordinates of a canvas.
+fn fits(position: Option<u16>) -> Option<u16> {
+    position.filter(|position| position.checked_add(8).is_some())
+}
 
-            let state_c = state.clone();
-            spawn(move || ws_thread(stream, state_c));
+fn device_thread(state_mutex: Arc<Mutex<State>>, ids: Vec<Option<String>>, slots: Arc<Slots>) {
+    let mut current: Vec<Option<(String, Arc<AtomicBool>)>> = vec![None; ids.len()];
+    let mut spare = Vec::new();
+    // Devices that couldn't be opened stay spare and are retried, their error is shown once.
+    let mut failed: Vec<String> = Vec::new();
+    for id in ids.iter() {
+        match id {
+            Some(id) => println!("Waiting for Launchpad {}", id),
+            None => println!("Waiting for a Launchpad"),
         }
-    } else {
-        print!("No Launchpad found");
-    }
-    Ok(())
-}
-
-fn ws_thread(stream: TcpStream, state_mutex: Arc<Mutex<State>>) {
-    let (tx, rx) = mpsc::channel();
-    let index = state_mutex.lock().unwrap().out_vec.push_empty(tx);
-    let pos = index_to_pos(index as _);
-    state_mutex.lock().unwrap().out_pad.set_color(pos, 0x11.into()).unwrap();
-
-    let mut websocket = accept(stream).unwrap();
-    'l: loop {
-        match websocket.read_message() {
-            Err(WsError::ConnectionClosed) | Err(WsError::AlreadyClosed) => break 'l,
-            Ok(Message::Text(msg)) => {
-                match &msg[..] {
-                    "1" => {
-                        let mut state = state_mutex.lock().unwrap();
-                        if state.current == Some(index as _) {
-                            state.out_pad.set_color(pos, Color::GREEN).unwrap();
-                            state.out_pad.set_color((0, 8), Color::GREEN).unwrap();
-                        } else {
-                            state.out_pad.set_color(pos, 0x10.into()).unwrap();
-                        }
-                    }
-                    "2" => {
-                        let mut state = state_mutex.lock().unwrap();
-                        if state.current == Some(index as _) {
-                            state.out_pad.set_color(pos, Color::RED).unwrap();
-                            state.out_pad.set_color((0, 8), Color::RED).unwrap();
-                        } else {
-                            state.out_pad.set_color(pos, 0x01.into()).unwrap();
-                        }
+    }
+    let events = hotplug::watch_launchpads(WATCH_INTERVAL);
+    loop {
+        match events.recv_timeout(WATCH_INTERVAL) {
+            Ok(DeviceEvent::Arrived(uninit_pad)) => {
+                if ids
+                    .iter()
+                    .any(|id| id.as_ref().is_none_or(|id| id == uninit_pad.id()))
+                {
+                    spare.push(uninit_pad);
+                }
+            }
+            Ok(DeviceEvent::Removed(id)) => {
+                spare.retain(|uninit_pad| uninit_pad.id() != id);
+                failed.retain(|failed| *failed != id);
+                let connected = current
+                    .iter()
+                    .position(|c| c.as_ref().map(|(c, _)| c) == Some(&id));
+                if let Some(device) = connected {
+                    println!("{} was removed, waiting for a Launchpad", id);
+                    if let Some((_, alive)) = current[device].take() {
+                        alive.store(false, Ordering::Relaxed);
                     }
-                    _ => (),
+                    state_mutex.lock().unwrap().out_pad.detach(device);
                 }
             }
-            _ => (),
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => return,
         }
 
-        for string in rx.try_iter() {
-            if websocket.write_message(Message::Text(string)).is_err() {
-                break 'l;
+        // Devices given by id get their own place, the others fill the places for any device.
+        let mut index = 0;
+        while index < spare.len() {
+            let uninit_pad = &spare[index];
+            let id = uninit_pad.id();
+            let free = |device: &usize| current[*device].is_none();
+            let device = (0..ids.len())
+                .filter(free)
+                .find(|&device| ids[device].as_deref() == Some(id))
+                .or_else(|| (0..ids.len()).filter(free).find(|&d| ids[d].is_none()));
+            let device = match device {
+                Some(device) => device,
+                None => {
+                    index += 1;
+                    continue;
+                }
+            };
+            match connect(uninit_pad, device, &state_mutex, &slots) {
+                Ok(alive) => {
+                    failed.retain(|failed| failed != id);
+                    current[device] = Some((id.to_owned(), alive));
+                    spare.remove(index);
+                }
+                // Another program may have it open.
+                Err(err) => {
+                    if !failed.iter().any(|failed| failed == id) {
+                        eprintln!("Failed to open {}, retrying: {}", uninit_pad.name(), err);
+                        failed.push(id.to_owned());
+                    }
+                    index += 1;
+                }
             }
         }
     }
+}
 
-    let mut state = state_mutex.lock().unwrap();
-    state.out_pad.set_color(pos, Color::BLACK).unwrap();
-    state.out_vec.take_at(index);
-}
-
-fn pad_thread(mut in_pad: LaunchpadIn, state_mutex: Arc<Mutex<State>>) {
-    let msgs = in_pad.msgs();
-    for event in msgs {
-        match event {
-            Event::Down((x, y @ 1..=7)) => {
-                let mut state = state_mutex.lock().unwrap();
-                let index = pos_to_index((x, y));
-                if state.current != Some(index) && state.out_vec.get(index as usize).is_some() {
-                    state.current.map(|current| {
-                        let pos = index_to_pos(current);
-                        let col = (u8::from(state.out_pad.get_color(pos)) / 3).into();
-                        state.out_pad.set_color(pos, col).unwrap();
-                    });
-
-                    let pos = (x, y);
-                    let col = (u8::from(state.out_pad.get_color(pos)) * 3).into();
-                    state.out_pad.set_color(pos, col).unwrap();
-                    state.out_pad.set_color((0, 8), col).unwrap();
-
-                    state.current = Some(index);
+/// Opens a device in the place `device` and starts reading its pads.
+///
+/// Returns the flag that stops the reading thread.
+fn connect(
+    uninit_pad: &UninitLaunchpad,
+    device: usize,
+    state_mutex: &Arc<Mutex<State>>,
+    slots: &Arc<Slots>,
+) -> LaunchpadResult<Arc<AtomicBool>> {
+    let (in_pad, out_pad) = uninit_pad.init()?;
+
+    println!(
+        "Connected to {} ({}, {:?})",
+        uninit_pad.name(),
+        uninit_pad.id(),
+        uninit_pad.model().unwrap()
+    );
+    if let Err(err) = state_mutex.lock().unwrap().out_pad.attach(device, out_pad) {
+        eprintln!("Failed to repaint {}: {}", uninit_pad.name(), err);
+    }
+
+    let alive = Arc::new(AtomicBool::new(true));
+    let alive_c = alive.clone();
+    let state_c = state_mutex.clone();
+    let slots_c = slots.clone();
+    spawn(move || pad_thread(in_pad, device, state_c, slots_c, alive_c));
+    Ok(alive)
+}
+
+async fn serve_client(
+    stream: TcpStream,
+    state_mutex: Arc<Mutex<State>>,
+    slots: Arc<Slots>,
+    grace: Duration,
+) {
+    let mut websocket = match tokio_tungstenite::accept_async(stream).await {
+        Ok(websocket) => websocket,
+        Err(err) => {
+            eprintln!("Websocket handshake failed: {}", err);
+            return;
+        }
+    };
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mut index = blocking(&state_mutex, &slots, move |state_mutex, slots| {
+        add_client(state_mutex, slots, tx)
+    })
+    .await;
+
+    let mut mode = Mode::Legacy;
+    let mut token = None;
+    loop {
+        let reply = tokio::select! {
+            msg = websocket.next() => match msg {
+                Some(Ok(Message::Text(msg))) => match mode.decode(&msg) {
+                    Ok(Some(ClientMsg::Hello { id, resume, .. })) => {
+                        let session = match resume.clone() {
+                            Some(resume) => {
+                                blocking(&state_mutex, &slots, move |state_mutex, slots| {
+                                    resume_client(state_mutex, slots, index, &resume)
+                                })
+                                .await
+                            }
+                            None => None,
+                        };
+                        let resumed = session.is_some();
+                        if let Some(session) = session {
+                            (index, rx) = session;
+                            token = resume;
+                        } else if let Some(id) = id {
+                            index = blocking(&state_mutex, &slots, move |state_mutex, slots| {
+                                claim_slot(state_mutex, slots, index, id)
+                            })
+                            .await;
+                        }
+                        let token = token.get_or_insert_with(new_token);
+                        Some(ServerMsg::Hello {
+                            version: protocol::VERSION,
+                            slot: index,
+                            token: token.clone(),
+                            resumed,
+                        })
+                    }
+                    Ok(Some(ClientMsg::SetStatus { status })) => {
+                        let color = status_color(status);
+                        blocking(&state_mutex, &slots, move |state_mutex, slots| {
+                            set_client_color(state_mutex, slots, index, color)
+                        })
+                        .await;
+                        None
+                    }
+                    Ok(Some(ClientMsg::SetColor { color })) => {
+                        let color = Rgb::new(color.r, color.g, color.b).into();
+                        blocking(&state_mutex, &slots, move |state_mutex, slots| {
+                            set_client_color(state_mutex, slots, index, color)
+                        })
+                        .await;
+                        None
+                    }
+                    Ok(Some(ClientMsg::Subscribe { events })) => {
+                        mode.subscribe(events);
+                        None
+                    }
+                    Ok(None) => None,
+                    Err(err) => Some(ServerMsg::Error {
+                        message: err.to_string(),
+                    }),
+                },
+                Some(Ok(_)) => None,
+                Some(Err(_)) | None => break,
+            },
+            msg = rx.recv() => match msg {
+                Some(msg) => Some(msg),
+                // The client was kicked, its slot is already free and may be taken again.
+                None => {
+                    let _ = websocket.close(None).await;
+                    return;
                 }
+            },
+        };
+
+        if let Some(text) = reply.and_then(|reply| mode.encode(&reply)) {
+            if websocket.send(Message::Text(text)).await.is_err() {
+                break;
             }
-            Event::Down((x @ 0..=4, 8)) => {
-                let state = state_mutex.lock().unwrap();
-                if let Some(tx) = state
-                    .current
-                    .and_then(|c| state.out_vec.get_inner(c as usize))
-                {
-                    tx.send(x.to_string()).unwrap();
+        }
+    }
+
+    match token {
+        Some(token) => detach_client(&state_mutex, &slots, index, token, rx, grace).await,
+        // Legacy clients can't resume.
+        None => {
+            blocking(&state_mutex, &slots, move |state_mutex, slots| {
+                remove_client(&mut state_mutex.lock().unwrap(), slots, index)
+            })
+            .await
+        }
+    }
+}
+
+/// Runs `f` on a thread that may block, the state lock is held while LEDs are written.
+async fn blocking<T, F>(state_mutex: &Arc<Mutex<State>>, slots: &Arc<Slots>, f: F) -> T
+where
+    F: FnOnce(&Mutex<State>, &Slots) -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let state_c = state_mutex.clone();
+    let slots_c = slots.clone();
+    tokio::task::spawn_blocking(move || f(&state_c, &slots_c))
+        .await
+        .unwrap()
+}
+
+fn new_token() -> String {
+    format!("{:032x}", rand::random::<u128>())
+}
+
+/// Keeps the pad of a dropped client for the `grace` period, queueing its events.
+async fn detach_client(
+    state_mutex: &Arc<Mutex<State>>,
+    slots: &Arc<Slots>,
+    index: usize,
+    token: String,
+    rx: mpsc::UnboundedReceiver<ServerMsg>,
+    grace: Duration,
+) {
+    let until = Instant::now() + grace;
+    let detached = Detached { index, rx, until };
+    let token_c = token.clone();
+    blocking(state_mutex, slots, move |state_mutex, _| {
+        state_mutex
+            .lock()
+            .unwrap()
+            .detached
+            .insert(token_c, detached);
+    })
+    .await;
+
+    let state_c = state_mutex.clone();
+    let slots_c = slots.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep_until(until.into()).await;
+        blocking(&state_c, &slots_c, move |state_mutex, slots| {
+            expire_client(&mut state_mutex.lock().unwrap(), slots, &token)
+        })
+        .await
+    });
+}
+
+/// Frees the slot of the session of `token` once its grace period is over.
+fn expire_client(state: &mut State, slots: &Slots, token: &str) {
+    // The client may have resumed and dropped again since, with a later deadline.
+    let expired = state
+        .detached
+        .get(token)
+        .filter(|detached| detached.until <= Instant::now());
+    if let Some(index) = expired.map(|detached| detached.index) {
+        remove_client(state, slots, index);
+    }
+}
+
+/// Gives a new connection the first free slot, where it shows as idle.
+fn add_client(
+    state_mutex: &Mutex<State>,
+    slots: &Slots,
+    tx: mpsc::UnboundedSender<ServerMsg>,
+) -> usize {
+    let col = status_color(Status::Idle);
+    let index = {
+        let mut state = state_mutex.lock().unwrap();
+        let index = state.free_slot();
+        state.out_vec.put_at(index, Client { tx, color: col });
+        index
+    };
+    set_client_color(state_mutex, slots, index, col);
+    index
+}
+
+/// Hands the session of `token` to the client in slot `index`, freeing that slot.
+///
+/// Returns the slot of the session and its queued events.
+fn resume_client(
+    state_mutex: &Mutex<State>,
+    slots: &Slots,
+    index: usize,
+    token: &str,
+) -> Option<(usize, mpsc::UnboundedReceiver<ServerMsg>)> {
+    let mut guard = state_mutex.lock().unwrap();
+    let state = &mut *guard;
+    let detached = state.detached.remove(token)?;
+    remove_client(state, slots, index);
+
+    // Freeing a slot drops its detached session, so the client is still there.
+    let client = state.out_vec.get_inner(detached.index).unwrap();
+    let selected = state.current == Some(detached.index);
+    let page = state.page;
+    let mut frame = state.out_pad.frame();
+    paint_client(
+        &mut frame,
+        slots,
+        page,
+        detached.index,
+        client.color,
+        selected,
+    );
+    commit(frame);
+    Some((detached.index, detached.rx))
+}
+
+/// Frees a slot and blacks out its pad, dropping the focus if it was selected.
+///
+/// Dropping the client's sender makes its connection close.
+fn remove_client(state: &mut State, slots: &Slots, index: usize) {
+    state.detached.retain(|_, detached| detached.index != index);
+    if state.out_vec.take_at(index).is_none() {
+        return;
+    }
+    let selected = state.current == Some(index);
+    if selected {
+        state.current = None;
+    }
+    let page = state.page;
+    let pages = state.pages(slots);
+    let mut frame = state.out_pad.frame();
+    if let Some(pad) = slots.pad(page, index) {
+        frame.set_color(pad, Color::BLACK);
+    }
+    if selected {
+        frame.set_color(slots.legend(0), Color::BLACK);
+    }
+    paint_pages(&mut frame, slots, page, pages);
+    commit(frame);
+}
+
+/// Moves a client to the slot of its `id`, reserving its current slot for an unknown id.
+///
+/// A dropped session of the id in the slot is replaced, along with its focus. Returns the slot
+/// the client ends up in, which stays the same while another connection occupies the slot.
+fn claim_slot(state_mutex: &Mutex<State>, slots: &Slots, index: usize, id: String) -> usize {
+    let mut guard = state_mutex.lock().unwrap();
+    let state = &mut *guard;
+    let slot = *state.reserved.entry(id.clone()).or_insert(index);
+    if slot == index {
+        return index;
+    }
+    // The client restarted and lost its resume token.
+    let replaced = state
+        .detached
+        .values()
+        .any(|detached| detached.index == slot);
+    let took_focus = replaced && state.current == Some(slot);
+    if replaced {
+        remove_client(state, slots, slot);
+    }
+    if state.out_vec.get_inner(slot).is_some() {
+        eprintln!("The pad of {} is taken, it keeps slot {}", id, index);
+        return index;
+    }
+
+    let client = state.out_vec.take_at(index).unwrap();
+    let col = client.color;
+    state.out_vec.put_at(slot, client);
+    let selected = state.current == Some(index) || took_focus;
+    if selected {
+        state.current = Some(slot);
+    }
+    let page = state.page;
+    let pages = state.pages(slots);
+    let mut frame = state.out_pad.frame();
+    if let Some(pad) = slots.pad(page, index) {
+        frame.set_color(pad, Color::BLACK);
+    }
+    paint_client(&mut frame, slots, page, slot, col, selected);
+    paint_pages(&mut frame, slots, page, pages);
+    commit(frame);
+    slot
+}
+
+/// Sends a frame, a failure leaves the LEDs behind until the next update.
+fn commit(frame: CanvasFrame) {
+    if let Err(err) = frame.commit() {
+        eprintln!("Failed to update the Launchpad: {}", err);
+    }
+}
+
+fn status_color(status: Status) -> PadColor {
+    match status {
+        Status::Idle => Color::ORANGE.into(),
+        Status::Ok => Color::GREEN.into(),
+        Status::Error => Color::RED.into(),
+    }
+}
+
+/// Unselected clients are shown at a third of their color.
+fn dimmed(col: PadColor) -> PadColor {
+    match col {
+        PadColor::RedGreen(col) => Color::new(u8::from(col) / 3).into(),
+        PadColor::Rgb(Rgb { r, g, b }) => Rgb::new(r / 3, g / 3, b / 3).into(),
+        col => col,
+    }
+}
+
+/// Shows a client's color on its pad if it is on the shown `page` and, while it is selected,
+/// on the legend.
+fn paint_client(
+    frame: &mut CanvasFrame,
+    slots: &Slots,
+    page: usize,
+    index: usize,
+    col: PadColor,
+    selected: bool,
+) {
+    if selected {
+        frame.set_color(slots.legend(0), col);
+    }
+    if let Some(pad) = slots.pad(page, index) {
+        frame.set_color(pad, if selected { col } else { dimmed(col) });
+    }
+}
+
+/// Lights a legend pad right of the answers for every page, the shown one brightly.
+///
+/// Pages past the last pad share it.
+fn paint_pages(frame: &mut CanvasFrame, slots: &Slots, page: usize, pages: usize) {
+    let first = LEGEND_ANSWERS + 1;
+    let count = slots.legend.len() - first;
+    for pos in 0..count {
+        let col = if pos == page.min(count - 1) {
+            Color::YELLOW.into()
+        } else if pos < pages {
+            dimmed(Color::YELLOW.into())
+        } else {
+            Color::BLACK.into()
+        };
+        frame.set_color(slots.legend(first + pos), col);
+    }
+}
+
+fn set_client_color(state_mutex: &Mutex<State>, slots: &Slots, index: usize, col: PadColor) {
+    let mut guard = state_mutex.lock().unwrap();
+    let state = &mut *guard;
+    match state.out_vec.get_mut(index).and_then(Option::as_mut) {
+        Some(client) => client.color = col,
+        None => return,
+    }
+    let selected = state.current == Some(index);
+    let page = state.page;
+    let pages = state.pages(slots);
+    let mut frame = state.out_pad.frame();
+    paint_client(&mut frame, slots, page, index, col, selected);
+    paint_pages(&mut frame, slots, page, pages);
+    commit(frame);
+}
+
+fn pad_thread(
+    in_pad: LaunchpadIn,
+    device: usize,
+    state_mutex: Arc<Mutex<State>>,
+    slots: Arc<Slots>,
+    alive: Arc<AtomicBool>,
+) {
+    let placement = state_mutex.lock().unwrap().out_pad.placement(device);
+    let mut gestures = Gestures::new(in_pad, GestureConfig::default());
+    while alive.load(Ordering::Relaxed) {
+        let gesture = match gestures.recv_timeout(PAD_POLL_INTERVAL) {
+            Ok(gesture) => gesture,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+
+        match gesture {
+            // A quick second press still counts as a press of its own.
+            Gesture::Tap(pad) | Gesture::DoubleTap(pad) => match placement.to_canvas(device, pad) {
+                CanvasPad::Grid { x, y } => match slots.legend_position(x, y) {
+                    Some(button) if button <= LEGEND_ANSWERS => {
+                        let state = state_mutex.lock().unwrap();
+                        let current = state.current.and_then(|c| state.out_vec.get_inner(c));
+                        if let Some(client) = current {
+                            // The client's thread may be gone already.
+                            let button = button as u8;
+                            let _ = client.tx.send(ServerMsg::Button { button });
+                        }
+                    }
+                    Some(_) => (),
+                    None => select_client(&state_mutex, &slots, x, y),
+                },
+                CanvasPad::Button {
+                    pad: Pad::UP | Pad::LEFT,
+                    ..
+                } => turn_page(&state_mutex, &slots, false),
+                CanvasPad::Button {
+                    pad: Pad::DOWN | Pad::RIGHT,
+                    ..
+                } => turn_page(&state_mutex, &slots, true),
+                _ => (),
+            },
+            Gesture::LongPress(pad) => {
+                if let CanvasPad::Grid { x, y } = placement.to_canvas(device, pad) {
+                    kick_client(&state_mutex, &slots, x, y)
                 }
             }
             _ => (),
@@ -171,10 +874,320 @@ fn pad_thread(mut in_pad: LaunchpadIn, state_mutex: Arc<Mutex<State>>) {
     }
 }
 
-fn index_to_pos(index: u8) -> (u8, u8) {
-    (index % 8, (index / 8) + 1)
+fn select_client(state_mutex: &Mutex<State>, slots: &Slots, x: u16, y: u16) {
+    let mut guard = state_mutex.lock().unwrap();
+    let state = &mut *guard;
+    let index = match slots.index(state.page, x, y) {
+        Some(index) => index,
+        None => return,
+    };
+    if state.current == Some(index) || state.out_vec.get_inner(index).is_none() {
+        return;
+    }
+
+    let previous = state.current.replace(index);
+    let page = state.page;
+    let mut frame = state.out_pad.frame();
+    let focus = previous.map(|previous| (previous, false)).into_iter();
+    for (index, selected) in focus.chain(Some((index, true))) {
+        if let Some(client) = state.out_vec.get_inner(index) {
+            paint_client(&mut frame, slots, page, index, client.color, selected);
+            let _ = client
+                .tx
+                .send(ServerMsg::FocusChanged { focused: selected });
+        }
+    }
+    commit(frame);
+}
+
+fn kick_client(state_mutex: &Mutex<State>, slots: &Slots, x: u16, y: u16) {
+    let mut state = state_mutex.lock().unwrap();
+    if let Some(index) = slots.index(state.page, x, y) {
+        remove_client(&mut state, slots, index);
+    }
 }
 
-fn pos_to_index(pos: (u8, u8)) -> u8 {
-    pos.0 + ((pos.1 - 1) * 8)
-}
\ No newline at end of file
+/// Shows the next or previous page, if there is one.
+fn turn_page(state_mutex: &Mutex<State>, slots: &Slots, forward: bool) {
+    let mut guard = state_mutex.lock().unwrap();
+    let state = &mut *guard;
+    let page = if forward {
+        state.page + 1
+    } else {
+        match state.page.checked_sub(1) {
+            Some(page) => page,
+            None => return,
+        }
+    };
+    if page >= state.pages(slots) {
+        return;
+    }
+
+    state.page = page;
+    let pages = state.pages(slots);
+    let mut frame = state.out_pad.frame();
+    for (cell, &(x, y)) in slots.cells.iter().enumerate() {
+        let index = page * slots.per_page() + cell;
+        match state.out_vec.get_inner(index) {
+            Some(client) => {
+                let selected = state.current == Some(index);
+                paint_client(&mut frame, slots, page, index, client.color, selected);
+            }
+            None => frame.set_color(CanvasPad::Grid { x, y }, Color::BLACK),
+        }
+    }
+    paint_pages(&mut frame, slots, page, pages);
+    commit(frame);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_midi::{self, MockHandle, Registry};
+    use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+    const RECV_TIMEOUT: Duration = Duration::from_secs(2);
+
+    type ClientSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+    fn canvas(positions: &[(u16, u16)]) -> Canvas {
+        let mut canvas = Canvas::new();
+        for &(x, y) in positions {
+            let orientation = Orientation::default();
+            canvas.add_device(Placement { x, y, orientation });
+        }
+        canvas
+    }
+
+    #[test]
+    fn slots_skip_uncovered_cells() {
+        let slots = Slots::new(&canvas(&[(8, 0)]));
+        assert_eq!(slots.per_page(), 56);
+        assert_eq!(slots.pad(0, 0), Some(CanvasPad::Grid { x: 8, y: 0 }));
+        assert_eq!(slots.legend(0), CanvasPad::Grid { x: 8, y: 7 });
+        assert_eq!(slots.index(0, 0, 0), None);
+        assert_eq!(slots.index(1, 9, 1), Some(56 + 9));
+        assert_eq!(slots.legend_position(15, 7), Some(7));
+    }
+
+    #[test]
+    fn staggered_legend_is_on_the_lowest_device() {
+        let slots = Slots::new(&canvas(&[(0, 0), (8, 4)]));
+        assert_eq!(slots.per_page(), 64 + 56);
+        assert_eq!(slots.legend.len(), 8);
+        assert_eq!(slots.legend(0), CanvasPad::Grid { x: 8, y: 11 });
+        assert_eq!(slots.index(0, 8, 4), Some(4 * 8 + 8));
+        assert_eq!(slots.index(0, 0, 7), Some(4 * 8 + 3 * 16));
+        assert_eq!(slots.index(0, 8, 11), None);
+    }
+
+    fn plug_launchpad_s() -> MockHandle {
+        let device = mock_midi::add_device("Launchpad S");
+        device.set_identity(&[
+            0xF0, 0x7E, 0x00, 0x06, 0x02, 0x00, 0x20, 0x29, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x01, 0xF7,
+        ]);
+        device
+    }
+
+    /// Plugs in a Launchpad S and connects it like `main` does.
+    fn start_devices(canvas: Canvas) -> (MockHandle, Arc<Mutex<State>>, Arc<Slots>) {
+        let device = plug_launchpad_s();
+        let slots = Arc::new(Slots::new(&canvas));
+        let state_mutex = Arc::new(Mutex::new(State::new(canvas, HashMap::new())));
+
+        let registry = Registry::current();
+        let state_c = state_mutex.clone();
+        let slots_c = slots.clone();
+        spawn(move || {
+            registry.enter();
+            device_thread(state_c, vec![None], slots_c)
+        });
+        let deadline = Instant::now() + RECV_TIMEOUT;
+        while !state_mutex.lock().unwrap().out_pad.is_attached(0) {
+            assert!(Instant::now() < deadline, "the device wasn't connected");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        (device, state_mutex, slots)
+    }
+
+    /// Serves one client on a free port and connects to it.
+    async fn connect_client(state_mutex: Arc<Mutex<State>>, slots: Arc<Slots>) -> ClientSocket {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            serve_client(stream, state_mutex, slots, DEFAULT_GRACE).await
+        });
+        tokio_tungstenite::connect_async(url).await.unwrap().0
+    }
+
+    async fn recv(websocket: &mut ClientSocket) -> String {
+        match tokio::time::timeout(RECV_TIMEOUT, websocket.next()).await {
+            Ok(Some(Ok(Message::Text(text)))) => text,
+            msg => panic!("expected a text message, got {:?}", msg),
+        }
+    }
+
+    fn tap(device: &MockHandle, note: u8, at: Duration) {
+        assert!(device.inject_short([0x90, note, 0x7F], at));
+        assert!(device.inject_short([0x90, note, 0x00], at + Duration::from_millis(50)));
+    }
+
+    #[tokio::test]
+    async fn answers_reach_the_selected_client() {
+        let (device, state_mutex, slots) = start_devices(canvas(&[(0, 0)]));
+        device.take_sent();
+        let mut websocket = connect_client(state_mutex, slots).await;
+        let hello = r#"{"type": "hello", "version": 1}"#;
+        websocket
+            .send(Message::Text(hello.to_owned()))
+            .await
+            .unwrap();
+        let hello = recv(&mut websocket).await;
+        assert!(hello.starts_with(r#"{"type":"hello","version":1,"slot":0,"#));
+        // The pad of an unselected client is dimmed, the legend shows the first page.
+        assert_eq!(
+            device.take_sent(),
+            vec![[0x90, 0x00, 0x1D], [0x90, 0x75, 0x3D]]
+        );
+
+        // Select the client on its pad at 0,0, then press the first answer at 1,7.
+        tap(&device, 0x00, Duration::from_millis(0));
+        tap(&device, 0x71, Duration::from_millis(100));
+        assert_eq!(
+            recv(&mut websocket).await,
+            r#"{"type":"button","button":1}"#
+        );
+        assert_eq!(
+            device.take_sent(),
+            vec![[0x90, 0x00, 0x3F], [0x90, 0x70, 0x3F]]
+        );
+    }
+
+    const TOKEN: &str = "token";
+
+    /// The state of a Launchpad S at 0,0, without a device thread.
+    fn open_state() -> (Mutex<State>, Slots) {
+        plug_launchpad_s();
+        let uninit_pad = launchpad::enumerate_launchpads().next().unwrap();
+        let (_, out_pad) = uninit_pad.init().unwrap();
+        let mut canvas = canvas(&[(0, 0)]);
+        canvas.attach(0, out_pad).unwrap();
+        let slots = Slots::new(&canvas);
+        (Mutex::new(State::new(canvas, HashMap::new())), slots)
+    }
+
+    fn new_client(
+        state_mutex: &Mutex<State>,
+        slots: &Slots,
+    ) -> (usize, mpsc::UnboundedReceiver<ServerMsg>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (add_client(state_mutex, slots, tx), rx)
+    }
+
+    /// Keeps the session of a dropped connection like `detach_client`, without the timer.
+    fn detach(
+        state_mutex: &Mutex<State>,
+        index: usize,
+        rx: mpsc::UnboundedReceiver<ServerMsg>,
+        grace: Duration,
+    ) {
+        let until = Instant::now() + grace;
+        let detached = Detached { index, rx, until };
+        let mut state = state_mutex.lock().unwrap();
+        state.detached.insert(TOKEN.to_owned(), detached);
+    }
+
+    #[test]
+    fn resume_restores_slot_color_and_focus() {
+        let (state_mutex, slots) = open_state();
+        let (index, rx) = new_client(&state_mutex, &slots);
+        let green = status_color(Status::Ok);
+        set_client_color(&state_mutex, &slots, index, green);
+        select_client(&state_mutex, &slots, 0, 0);
+        detach(&state_mutex, index, rx, DEFAULT_GRACE);
+        {
+            // A press while the client is away.
+            let state = state_mutex.lock().unwrap();
+            let client = state.out_vec.get_inner(index).unwrap();
+            client.tx.send(ServerMsg::Button { button: 2 }).unwrap();
+        }
+
+        let (new_index, _) = new_client(&state_mutex, &slots);
+        assert_eq!(new_index, 1);
+        let (index, mut rx) = resume_client(&state_mutex, &slots, new_index, TOKEN).unwrap();
+        assert_eq!(index, 0);
+        let focused = ServerMsg::FocusChanged { focused: true };
+        assert_eq!(rx.try_recv(), Ok(focused));
+        assert_eq!(rx.try_recv(), Ok(ServerMsg::Button { button: 2 }));
+
+        let state = state_mutex.lock().unwrap();
+        assert_eq!(state.current, Some(0));
+        assert!(state.detached.is_empty());
+        assert!(state.out_vec.get_inner(new_index).is_none());
+        assert_eq!(state.out_pad.get_color(slots.pad(0, 0).unwrap()), green);
+        assert_eq!(state.out_pad.get_color(slots.legend(0)), green);
+        let black = PadColor::from(Color::BLACK);
+        assert_eq!(state.out_pad.get_color(slots.pad(0, 1).unwrap()), black);
+    }
+
+    #[test]
+    fn resume_with_an_unknown_token_keeps_the_slot() {
+        let (state_mutex, slots) = open_state();
+        let (index, _rx) = new_client(&state_mutex, &slots);
+        assert!(resume_client(&state_mutex, &slots, index, TOKEN).is_none());
+        assert!(state_mutex
+            .lock()
+            .unwrap()
+            .out_vec
+            .get_inner(index)
+            .is_some());
+    }
+
+    #[test]
+    fn expiry_frees_the_pad() {
+        let (state_mutex, slots) = open_state();
+        let (index, rx) = new_client(&state_mutex, &slots);
+        detach(&state_mutex, index, rx, DEFAULT_GRACE);
+        let mut state = state_mutex.lock().unwrap();
+
+        // The session resumed and dropped again, with a later deadline.
+        expire_client(&mut state, &slots, TOKEN);
+        assert!(state.out_vec.get_inner(index).is_some());
+
+        state.detached.get_mut(TOKEN).unwrap().until = Instant::now();
+        expire_client(&mut state, &slots, TOKEN);
+        assert!(state.out_vec.get_inner(index).is_none());
+        assert!(state.detached.is_empty());
+        let black = PadColor::from(Color::BLACK);
+        assert_eq!(state.out_pad.get_color(slots.pad(0, index).unwrap()), black);
+    }
+
+    #[test]
+    fn restarted_client_replaces_its_session() {
+        let (state_mutex, slots) = open_state();
+        let (index, rx) = new_client(&state_mutex, &slots);
+        assert_eq!(claim_slot(&state_mutex, &slots, index, "a".to_owned()), 0);
+        select_client(&state_mutex, &slots, 0, 0);
+        detach(&state_mutex, index, rx, DEFAULT_GRACE);
+
+        // The restarted client lost its token and says hello with its id.
+        let (new_index, mut rx) = new_client(&state_mutex, &slots);
+        assert_eq!(new_index, 1);
+        assert_eq!(
+            claim_slot(&state_mutex, &slots, new_index, "a".to_owned()),
+            0
+        );
+
+        let state = state_mutex.lock().unwrap();
+        assert!(state.detached.is_empty());
+        assert_eq!(state.current, Some(0));
+        assert!(state.out_vec.get_inner(new_index).is_none());
+        let idle = status_color(Status::Idle);
+        assert_eq!(state.out_pad.get_color(slots.pad(0, 0).unwrap()), idle);
+        let client = state.out_vec.get_inner(0).unwrap();
+        client.tx.send(ServerMsg::Button { button: 1 }).unwrap();
+        assert_eq!(rx.try_recv(), Ok(ServerMsg::Button { button: 1 }));
+    }
+}
@@ -0,0 +1,370 @@
+use std::sync::mpsc;
+use std::time::Duration;
+use thiserror::Error;
+
+#[cfg(all(target_os = "linux", not(test)))]
+pub type Backend = crate::alsa_midi::AlsaMidi;
+#[cfg(test)]
+pub type Backend = crate::mock_midi::MockMidi;
+#[cfg(all(windows, not(test)))]
+pub type Backend = crate::win_midi::WinMidi;
+
+pub type InCaps = <Backend as MidiBackend>::InCaps;
+pub type OutCaps = <Backend as MidiBackend>::OutCaps;
+pub type InDev = <Backend as MidiBackend>::InDev;
+pub type OutDev = <Backend as MidiBackend>::OutDev;
+
+#[allow(dead_code)]
+#[derive(Error, Debug)]
+pub enum MidiError {
+    #[error("MIDI device is in use by another application, close it and try again: {0}")]
+    Allocated(String),
+    #[error("MIDI device does not exist, it may have been unplugged: {0}")]
+    BadDeviceId(String),
+    #[error("MIDI device was disconnected: {0}")]
+    NoDevice(String),
+    #[error("No MIDI driver is installed for the device: {0}")]
+    NoDriver(String),
+    #[error("MIDI driver failed to enable the device: {0}")]
+    NotEnabled(String),
+    #[error("MIDI device handle is no longer valid: {0}")]
+    InvalidHandle(String),
+    #[error("Not enough memory for the MIDI driver: {0}")]
+    NoMem(String),
+    #[error("MIDI device is not ready, try again: {0}")]
+    NotReady(String),
+    #[error("MIDI error: {0}")]
+    Other(String),
+}
+
+impl MidiError {
+    /// Whether the device is gone, as opposed to a failure that may pass.
+    pub fn is_disconnect(&self) -> bool {
+        matches!(
+            self,
+            MidiError::NoDevice(_) | MidiError::BadDeviceId(_) | MidiError::InvalidHandle(_)
+        )
+    }
+}
+
+pub type MidiResult<T> = Result<T, MidiError>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MidiMsg {
+    pub timestamp: Duration,
+    pub event: MidiEvent,
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq)]
+pub enum MidiEvent {
+    NoteOff {
+        channel: u8,
+        note: u8,
+        velocity: u8,
+    },
+    NoteOn {
+        channel: u8,
+        note: u8,
+        velocity: u8,
+    },
+    PolyAftertouch {
+        channel: u8,
+        note: u8,
+        pressure: u8,
+    },
+    ControlChange {
+        channel: u8,
+        controller: u8,
+        value: u8,
+    },
+    ProgramChange {
+        channel: u8,
+        program: u8,
+    },
+    ChannelAftertouch {
+        channel: u8,
+        pressure: u8,
+    },
+    PitchBend {
+        channel: u8,
+        value: u16,
+    },
+    SysEx(Vec<u8>),
+    Realtime(u8),
+}
+
+impl MidiEvent {
+    pub fn from_short(data: [u8; 3]) -> Option<Self> {
+        let [status, data1, data2] = data;
+        let channel = status & 0xF;
+        match status & 0xF0 {
+            0x80 => Some(MidiEvent::NoteOff {
+                channel,
+                note: data1,
+                velocity: data2,
+            }),
+            // Note on with zero velocity is how most devices (the Launchpad included) send note off.
+            0x90 if data2 == 0 => Some(MidiEvent::NoteOff {
+                channel,
+                note: data1,
+                velocity: data2,
+            }),
+            0x90 => Some(MidiEvent::NoteOn {
+                channel,
+                note: data1,
+                velocity: data2,
+            }),
+            0xA0 => Some(MidiEvent::PolyAftertouch {
+                channel,
+                note: data1,
+                pressure: data2,
+            }),
+            0xB0 => Some(MidiEvent::ControlChange {
+                channel,
+                controller: data1,
+                value: data2,
+            }),
+            0xC0 => Some(MidiEvent::ProgramChange {
+                channel,
+                program: data1,
+            }),
+            0xD0 => Some(MidiEvent::ChannelAftertouch {
+                channel,
+                pressure: data1,
+            }),
+            0xE0 => Some(MidiEvent::PitchBend {
+                channel,
+                value: (data1 as u16 & 0x7F) | ((data2 as u16 & 0x7F) << 7),
+            }),
+            0xF0 if status >= 0xF8 => Some(MidiEvent::Realtime(status)),
+            _ => None,
+        }
+    }
+}
+
+/// Universal Device Inquiry addressed to all devices.
+pub const DEVICE_INQUIRY: [u8; 6] = [0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7];
+
+/// Reply to a Universal Device Inquiry.
+#[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeviceIdentity {
+    pub manufacturer: Vec<u8>,
+    pub family: u16,
+    pub model: u16,
+    pub version: [u8; 4],
+}
+
+impl DeviceIdentity {
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let data = match data {
+            [0xF0, 0x7E, _, 0x06, 0x02, data @ .., 0xF7] => data,
+            _ => return None,
+        };
+        // Manufacturer ids are either a single byte or zero followed by two bytes.
+        let (manufacturer, data) = match data {
+            [0, ..] if data.len() >= 3 => data.split_at(3),
+            _ if !data.is_empty() => data.split_at(1),
+            _ => return None,
+        };
+        match *data {
+            [family_lsb, family_msb, model_lsb, model_msb, v0, v1, v2, v3] => Some(Self {
+                manufacturer: manufacturer.to_vec(),
+                family: u16::from_le_bytes([family_lsb, family_msb]),
+                model: u16::from_le_bytes([model_lsb, model_msb]),
+                version: [v0, v1, v2, v3],
+            }),
+            _ => None,
+        }
+    }
+}
+
+pub trait MidiBackend {
+    type InCaps: PortCaps;
+    type OutCaps: PortCaps;
+    type InDev: MidiIn;
+    type OutDev: MidiOut;
+
+    fn enumerate_in() -> Vec<Self::InCaps>;
+    fn enumerate_out() -> Vec<Self::OutCaps>;
+    fn open_in(in_caps: &Self::InCaps) -> MidiResult<Self::InDev>;
+    fn open_out(out_caps: &Self::OutCaps) -> MidiResult<Self::OutDev>;
+}
+
+pub trait PortCaps {
+    fn name(&self) -> &str;
+    /// Identifies the device the port belongs to.
+    ///
+    /// The input and output port of one device share the same id, which stays the same
+    /// across enumerations, replugs of other devices and restarts as long as the device
+    /// stays on the same USB port. See `port_ids`.
+    fn id(&self) -> &str;
+}
+
+/// Ids of ports from their names and where their devices are connected, e.g. a USB port.
+///
+/// Ports with the same id left, those of a device with several ports of one name or
+/// those of devices at unknown locations, are numbered with `number_instances`.
+pub fn port_ids(ports: Vec<(String, Option<String>)>) -> Vec<String> {
+    let ids = ports
+        .into_iter()
+        .map(|(name, location)| match location {
+            Some(location) => format!("{} ({})", name, location),
+            None => name,
+        })
+        .collect();
+    number_instances(ids)
+}
+
+/// Makes repeated ids unique by appending `#2`, `#3`, ... in the order they appear.
+pub fn number_instances(ids: Vec<String>) -> Vec<String> {
+    ids.iter()
+        .enumerate()
+        .map(
+            |(i, id)| match ids[..i].iter().filter(|prev| *prev == id).count() {
+                0 => id.clone(),
+                count => format!("{}#{}", id, count + 1),
+            },
+        )
+        .collect()
+}
+
+pub trait MidiIn {
+    fn start(&mut self) -> MidiResult<()>;
+    #[allow(dead_code)]
+    fn stop(&mut self) -> MidiResult<()>;
+    fn receiver(&self) -> &mpsc::Receiver<MidiMsg>;
+
+    fn current_msgs(&mut self) -> mpsc::TryIter<'_, MidiMsg> {
+        self.receiver().try_iter()
+    }
+
+    fn msgs(&mut self) -> mpsc::Iter<'_, MidiMsg> {
+        self.receiver().iter()
+    }
+}
+
+pub trait MidiOut {
+    fn send(&mut self, msg: u8, dw1: u8, dw2: u8) -> MidiResult<()>;
+    #[allow(dead_code)]
+    fn send_sysex(&mut self, data: &[u8]) -> MidiResult<()>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_short() {
+        let note_on = MidiEvent::NoteOn {
+            channel: 2,
+            note: 0x40,
+            velocity: 0x7F,
+        };
+        assert_eq!(MidiEvent::from_short([0x92, 0x40, 0x7F]), Some(note_on));
+        let note_off = MidiEvent::NoteOff {
+            channel: 0,
+            note: 0x40,
+            velocity: 0x10,
+        };
+        assert_eq!(MidiEvent::from_short([0x80, 0x40, 0x10]), Some(note_off));
+        let control = MidiEvent::ControlChange {
+            channel: 0,
+            controller: 0x68,
+            value: 0x7F,
+        };
+        assert_eq!(MidiEvent::from_short([0xB0, 0x68, 0x7F]), Some(control));
+        let bend = MidiEvent::PitchBend {
+            channel: 0,
+            value: 0x2000,
+        };
+        assert_eq!(MidiEvent::from_short([0xE0, 0x00, 0x40]), Some(bend));
+    }
+
+    #[test]
+    fn from_short_note_on_without_velocity_is_note_off() {
+        let note_off = MidiEvent::NoteOff {
+            channel: 0,
+            note: 0x11,
+            velocity: 0,
+        };
+        assert_eq!(MidiEvent::from_short([0x90, 0x11, 0x00]), Some(note_off));
+    }
+
+    #[test]
+    fn from_short_system_messages() {
+        assert_eq!(
+            MidiEvent::from_short([0xF8, 0, 0]),
+            Some(MidiEvent::Realtime(0xF8))
+        );
+        assert_eq!(MidiEvent::from_short([0xF2, 0x10, 0x20]), None);
+        assert_eq!(MidiEvent::from_short([0x40, 0x10, 0x20]), None);
+    }
+
+    #[test]
+    fn port_ids_by_location() {
+        let port =
+            |name: &str, location: Option<&str>| (name.to_owned(), location.map(str::to_owned));
+        let ports = vec![
+            port("Launchpad S", Some("usb-2")),
+            port("Launchpad S", Some("usb-1")),
+            port("Launchpad Pro", Some("usb-3")),
+            port("Launchpad Pro", Some("usb-3")),
+            port("Virtual", None),
+            port("Virtual", None),
+        ];
+        let ids = vec![
+            "Launchpad S (usb-2)",
+            "Launchpad S (usb-1)",
+            "Launchpad Pro (usb-3)",
+            "Launchpad Pro (usb-3)#2",
+            "Virtual",
+            "Virtual#2",
+        ];
+        assert_eq!(port_ids(ports), ids);
+    }
+
+    #[test]
+    fn parse_identity() {
+        let reply = [
+            0xF0, 0x7E, 0x00, 0x06, 0x02, 0x00, 0x20, 0x29, 0x03, 0x01, 0x12, 0x00, 0x01, 0x02,
+            0x03, 0x04, 0xF7,
+        ];
+        let identity = DeviceIdentity {
+            manufacturer: vec![0x00, 0x20, 0x29],
+            family: 0x0103,
+            model: 0x0012,
+            version: [1, 2, 3, 4],
+        };
+        assert_eq!(DeviceIdentity::parse(&reply), Some(identity));
+    }
+
+    #[test]
+    fn parse_identity_short_manufacturer() {
+        let reply = [
+            0xF0, 0x7E, 0x10, 0x06, 0x02, 0x41, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0xF7,
+        ];
+        let identity = DeviceIdentity::parse(&reply).unwrap();
+        assert_eq!(identity.manufacturer, vec![0x41]);
+        assert_eq!(identity.family, 0x0020);
+    }
+
+    #[test]
+    fn parse_identity_rejects_other_messages() {
+        assert_eq!(DeviceIdentity::parse(&DEVICE_INQUIRY), None);
+        assert_eq!(
+            DeviceIdentity::parse(&[0xF0, 0x7E, 0x00, 0x06, 0x02, 0xF7]),
+            None
+        );
+        let truncated = [
+            0xF0, 0x7E, 0x00, 0x06, 0x02, 0x00, 0x20, 0x29, 0x03, 0x01, 0xF7,
+        ];
+        assert_eq!(DeviceIdentity::parse(&truncated), None);
+        let unterminated = [
+            0xF0, 0x7E, 0x00, 0x06, 0x02, 0x41, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        assert_eq!(DeviceIdentity::parse(&unterminated), None);
+    }
+}
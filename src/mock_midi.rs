@@ -0,0 +1,259 @@
+//! Virtual MIDI devices for tests.
+//!
+//! Every thread starts out with a registry of its own, so tests running in parallel don't see
+//! each other's devices. Threads a test starts see its devices after entering its `Registry`,
+//! the hotplug watcher enters the registry of the thread that starts it. Handles and opened
+//! ports work from any thread.
+
+use crate::midi::{self, MidiBackend, MidiEvent, MidiIn, MidiMsg, MidiOut, MidiResult, PortCaps};
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+
+thread_local! {
+    static REGISTRY: RefCell<Registry> = RefCell::new(Registry::default());
+}
+
+/// The devices plugged in for one test.
+#[derive(Clone, Default)]
+pub struct Registry {
+    devices: Arc<Mutex<Vec<Arc<MockShared>>>>,
+}
+
+impl Registry {
+    /// The registry of this thread.
+    pub fn current() -> Self {
+        REGISTRY.with(|registry| registry.borrow().clone())
+    }
+
+    /// Makes this thread see the devices of the registry instead of its own.
+    pub fn enter(&self) {
+        REGISTRY.with(|registry| *registry.borrow_mut() = self.clone());
+    }
+}
+
+pub struct MockMidi;
+
+impl MidiBackend for MockMidi {
+    type InCaps = MockCaps;
+    type OutCaps = MockCaps;
+    type InDev = InDev;
+    type OutDev = OutDev;
+
+    fn enumerate_in() -> Vec<Self::InCaps> {
+        enumerate_devices()
+    }
+
+    fn enumerate_out() -> Vec<Self::OutCaps> {
+        enumerate_devices()
+    }
+
+    fn open_in(in_caps: &Self::InCaps) -> MidiResult<Self::InDev> {
+        Ok(InDev::new(in_caps.shared.clone()))
+    }
+
+    fn open_out(out_caps: &Self::OutCaps) -> MidiResult<Self::OutDev> {
+        Ok(OutDev {
+            shared: out_caps.shared.clone(),
+        })
+    }
+}
+
+struct MockShared {
+    name: String,
+    running: AtomicBool,
+    in_tx: Mutex<Option<mpsc::Sender<MidiMsg>>>,
+    sent: Mutex<Vec<[u8; 3]>>,
+    sent_sysex: Mutex<Vec<Vec<u8>>>,
+    /// Where the device is plugged in, like the USB port of a real one.
+    location: Option<String>,
+    /// The answer to device inquiries, the device stays silent without one.
+    identity: Mutex<Option<Vec<u8>>>,
+}
+
+impl MockShared {
+    fn inject(&self, msg: MidiMsg) -> bool {
+        if !self.running.load(Ordering::Relaxed) {
+            return false;
+        }
+
+        match &*self.in_tx.lock().unwrap() {
+            Some(tx) => tx.send(msg).is_ok(),
+            None => false,
+        }
+    }
+}
+
+fn enumerate_devices() -> Vec<MockCaps> {
+    let registry = Registry::current();
+    let devices = registry.devices.lock().unwrap();
+    let ports = devices
+        .iter()
+        .map(|shared| (shared.name.clone(), shared.location.clone()))
+        .collect();
+    let ids = midi::port_ids(ports);
+    devices
+        .iter()
+        .zip(ids)
+        .map(|(shared, id)| MockCaps {
+            name: shared.name.clone(),
+            id,
+            shared: shared.clone(),
+        })
+        .collect()
+}
+
+/// Plugs in a device with an input and an output port named `name` into the registry of this
+/// thread.
+#[allow(dead_code)]
+pub fn add_device(name: &str) -> MockHandle {
+    plug(name, None)
+}
+
+/// Like `add_device`, at a known `location`.
+#[allow(dead_code)]
+pub fn add_device_at(name: &str, location: &str) -> MockHandle {
+    plug(name, Some(location.to_owned()))
+}
+
+fn plug(name: &str, location: Option<String>) -> MockHandle {
+    let shared = Arc::new(MockShared {
+        name: name.to_owned(),
+        location,
+        running: AtomicBool::new(false),
+        in_tx: Mutex::new(None),
+        sent: Mutex::new(Vec::new()),
+        sent_sysex: Mutex::new(Vec::new()),
+        identity: Mutex::new(None),
+    });
+    let registry = Registry::current();
+    registry.devices.lock().unwrap().push(shared.clone());
+    MockHandle { shared, registry }
+}
+
+#[allow(dead_code)]
+#[derive(Clone)]
+pub struct MockHandle {
+    shared: Arc<MockShared>,
+    registry: Registry,
+}
+
+#[allow(dead_code)]
+impl MockHandle {
+    /// Sends `msg` to the input port, `false` if it isn't open and started.
+    pub fn inject(&self, msg: MidiMsg) -> bool {
+        self.shared.inject(msg)
+    }
+
+    /// Answers device inquiries with the SysEx `reply`.
+    pub fn set_identity(&self, reply: &[u8]) {
+        self.shared.identity.lock().unwrap().replace(reply.to_vec());
+    }
+
+    pub fn inject_short(&self, data: [u8; 3], timestamp: Duration) -> bool {
+        match MidiEvent::from_short(data) {
+            Some(event) => self.inject(MidiMsg { timestamp, event }),
+            None => false,
+        }
+    }
+
+    pub fn sent(&self) -> Vec<[u8; 3]> {
+        self.shared.sent.lock().unwrap().clone()
+    }
+
+    pub fn take_sent(&self) -> Vec<[u8; 3]> {
+        std::mem::take(&mut *self.shared.sent.lock().unwrap())
+    }
+
+    pub fn sent_sysex(&self) -> Vec<Vec<u8>> {
+        self.shared.sent_sysex.lock().unwrap().clone()
+    }
+
+    pub fn take_sent_sysex(&self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut *self.shared.sent_sysex.lock().unwrap())
+    }
+
+    /// Unplugs the device from the registry it was added to.
+    pub fn remove(&self) {
+        let mut devices = self.registry.devices.lock().unwrap();
+        devices.retain(|shared| !Arc::ptr_eq(shared, &self.shared));
+    }
+}
+
+pub struct MockCaps {
+    pub name: String,
+    pub id: String,
+    shared: Arc<MockShared>,
+}
+
+impl PortCaps for MockCaps {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+pub struct InDev {
+    shared: Arc<MockShared>,
+    msg_rx: mpsc::Receiver<MidiMsg>,
+}
+
+impl InDev {
+    fn new(shared: Arc<MockShared>) -> Self {
+        let (msg_tx, msg_rx) = mpsc::channel::<MidiMsg>();
+        shared.in_tx.lock().unwrap().replace(msg_tx);
+        Self { shared, msg_rx }
+    }
+}
+
+impl MidiIn for InDev {
+    fn start(&mut self) -> MidiResult<()> {
+        self.shared.running.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn stop(&mut self) -> MidiResult<()> {
+        self.shared.running.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn receiver(&self) -> &mpsc::Receiver<MidiMsg> {
+        &self.msg_rx
+    }
+}
+
+impl Drop for InDev {
+    fn drop(&mut self) {
+        self.shared.running.store(false, Ordering::Relaxed);
+        self.shared.in_tx.lock().unwrap().take();
+    }
+}
+
+pub struct OutDev {
+    shared: Arc<MockShared>,
+}
+
+impl MidiOut for OutDev {
+    fn send(&mut self, msg: u8, dw1: u8, dw2: u8) -> MidiResult<()> {
+        self.shared.sent.lock().unwrap().push([msg, dw1, dw2]);
+        Ok(())
+    }
+
+    fn send_sysex(&mut self, data: &[u8]) -> MidiResult<()> {
+        self.shared.sent_sysex.lock().unwrap().push(data.to_vec());
+        if data == midi::DEVICE_INQUIRY {
+            let identity = self.shared.identity.lock().unwrap().clone();
+            if let Some(reply) = identity {
+                self.shared.inject(MidiMsg {
+                    timestamp: Duration::from_secs(0),
+                    event: MidiEvent::SysEx(reply),
+                });
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,260 @@
+/// A row or column of the 8x8 grid, always in `0..8`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Coord(u8);
+
+impl Coord {
+    pub fn new(val: u8) -> Option<Self> {
+        if val < 8 {
+            Some(Self(val))
+        } else {
+            None
+        }
+    }
+
+    pub fn get(self) -> u8 {
+        self.0
+    }
+
+    pub fn all() -> impl Iterator<Item = Self> {
+        (0..8).map(Self)
+    }
+}
+
+impl From<Coord> for u8 {
+    fn from(coord: Coord) -> Self {
+        coord.0
+    }
+}
+
+/// Every LED button of a Launchpad.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Pad {
+    /// A cell of the grid, `x` counted from the left and `y` from the top.
+    Grid { x: Coord, y: Coord },
+    /// A button in the row above the grid, counted from the left.
+    Top(Coord),
+    /// A button in the column right of the grid, counted from the top.
+    Side(Coord),
+}
+
+#[allow(dead_code)]
+impl Pad {
+    pub const UP: Self = Pad::Top(Coord(0));
+    pub const DOWN: Self = Pad::Top(Coord(1));
+    pub const LEFT: Self = Pad::Top(Coord(2));
+    pub const RIGHT: Self = Pad::Top(Coord(3));
+    pub const SESSION: Self = Pad::Top(Coord(4));
+    pub const USER1: Self = Pad::Top(Coord(5));
+    pub const USER2: Self = Pad::Top(Coord(6));
+    pub const MIXER: Self = Pad::Top(Coord(7));
+
+    pub const VOL: Self = Pad::Side(Coord(0));
+    pub const PAN: Self = Pad::Side(Coord(1));
+    pub const SND_A: Self = Pad::Side(Coord(2));
+    pub const SND_B: Self = Pad::Side(Coord(3));
+    pub const STOP: Self = Pad::Side(Coord(4));
+    pub const TRK_ON: Self = Pad::Side(Coord(5));
+    pub const SOLO: Self = Pad::Side(Coord(6));
+    pub const ARM: Self = Pad::Side(Coord(7));
+}
+
+impl Pad {
+    pub const COUNT: usize = 80;
+
+    pub fn grid(x: u8, y: u8) -> Option<Self> {
+        Some(Pad::Grid {
+            x: Coord::new(x)?,
+            y: Coord::new(y)?,
+        })
+    }
+
+    #[allow(dead_code)]
+    pub fn top(x: u8) -> Option<Self> {
+        Coord::new(x).map(Pad::Top)
+    }
+
+    #[allow(dead_code)]
+    pub fn side(y: u8) -> Option<Self> {
+        Coord::new(y).map(Pad::Side)
+    }
+
+    /// All pads ordered by `index`: the grid row by row, then the side and the top buttons.
+    pub fn all() -> impl Iterator<Item = Self> {
+        let grid = Coord::all().flat_map(|y| Coord::all().map(move |x| Pad::Grid { x, y }));
+        grid.chain(Coord::all().map(Pad::Side))
+            .chain(Coord::all().map(Pad::Top))
+    }
+
+    /// A dense index in `0..Pad::COUNT`.
+    pub fn index(self) -> usize {
+        match self {
+            Pad::Grid { x, y } => y.0 as usize * 8 + x.0 as usize,
+            Pad::Side(y) => 64 + y.0 as usize,
+            Pad::Top(x) => 72 + x.0 as usize,
+        }
+    }
+}
+
+/// A clockwise rotation of the grid in quarter turns.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Rotation {
+    #[default]
+    None,
+    Cw90,
+    Cw180,
+    Cw270,
+}
+
+impl Rotation {
+    pub fn from_degrees(degrees: u16) -> Option<Self> {
+        match degrees {
+            0 => Some(Rotation::None),
+            90 => Some(Rotation::Cw90),
+            180 => Some(Rotation::Cw180),
+            270 => Some(Rotation::Cw270),
+            _ => None,
+        }
+    }
+}
+
+/// How a device is mounted: turned clockwise, then optionally mirrored left to right.
+///
+/// The button strips follow the grid, whichever strip ends up horizontal is the top row
+/// and the vertical one is the side column, each counted from the left or the top.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Orientation {
+    pub rotation: Rotation,
+    pub mirrored: bool,
+}
+
+impl Orientation {
+    /// The pad seen at the physical `pad`.
+    pub fn to_logical(self, pad: Pad) -> Pad {
+        // The strips lie on row -1 and column 8 around the grid.
+        let (x, y) = match pad {
+            Pad::Grid { x, y } => (x.0 as i8, y.0 as i8),
+            Pad::Top(x) => (x.0 as i8, -1),
+            Pad::Side(y) => (8, y.0 as i8),
+        };
+        let (x, y) = match self.rotation {
+            Rotation::None => (x, y),
+            Rotation::Cw90 => (7 - y, x),
+            Rotation::Cw180 => (7 - x, 7 - y),
+            Rotation::Cw270 => (y, 7 - x),
+        };
+        let x = if self.mirrored { 7 - x } else { x };
+        match (x, y) {
+            (x, -1) | (x, 8) => Pad::Top(Coord(x as u8)),
+            (-1, y) | (8, y) => Pad::Side(Coord(y as u8)),
+            (x, y) => Pad::Grid {
+                x: Coord(x as u8),
+                y: Coord(y as u8),
+            },
+        }
+    }
+
+    /// The physical pad seen as `pad`.
+    pub fn to_physical(self, pad: Pad) -> Pad {
+        Pad::all()
+            .find(|&physical| self.to_logical(physical) == pad)
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    const ROTATIONS: [Rotation; 4] = [
+        Rotation::None,
+        Rotation::Cw90,
+        Rotation::Cw180,
+        Rotation::Cw270,
+    ];
+
+    fn orientations() -> impl Iterator<Item = Orientation> {
+        ROTATIONS.iter().flat_map(|&rotation| {
+            [false, true]
+                .iter()
+                .map(move |&mirrored| Orientation { rotation, mirrored })
+        })
+    }
+
+    fn grid(x: u8, y: u8) -> Pad {
+        Pad::grid(x, y).unwrap()
+    }
+
+    #[test]
+    fn default_orientation_keeps_pads() {
+        let orientation = Orientation::default();
+        for pad in Pad::all() {
+            assert_eq!(orientation.to_logical(pad), pad);
+            assert_eq!(orientation.to_physical(pad), pad);
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        for orientation in orientations() {
+            let logical: HashSet<Pad> = Pad::all().map(|pad| orientation.to_logical(pad)).collect();
+            assert_eq!(logical.len(), Pad::COUNT, "{:?}", orientation);
+            for pad in Pad::all() {
+                let logical = orientation.to_logical(pad);
+                assert_eq!(orientation.to_physical(logical), pad, "{:?}", orientation);
+                let physical = orientation.to_physical(pad);
+                assert_eq!(orientation.to_logical(physical), pad, "{:?}", orientation);
+            }
+        }
+    }
+
+    #[test]
+    fn strips_stay_strips() {
+        for orientation in orientations() {
+            for pad in Pad::all() {
+                let is_grid = |pad| matches!(pad, Pad::Grid { .. });
+                assert_eq!(is_grid(orientation.to_logical(pad)), is_grid(pad));
+            }
+        }
+    }
+
+    #[test]
+    fn rotations() {
+        let turned = |rotation| Orientation {
+            rotation,
+            mirrored: false,
+        };
+        let cw90 = turned(Rotation::Cw90);
+        assert_eq!(cw90.to_logical(grid(0, 0)), grid(7, 0));
+        assert_eq!(cw90.to_logical(grid(1, 0)), grid(7, 1));
+        assert_eq!(cw90.to_logical(Pad::UP), Pad::Side(Coord(0)));
+        assert_eq!(cw90.to_logical(Pad::VOL), Pad::Top(Coord(7)));
+
+        let cw180 = turned(Rotation::Cw180);
+        assert_eq!(cw180.to_logical(grid(0, 0)), grid(7, 7));
+        assert_eq!(cw180.to_logical(Pad::UP), Pad::Top(Coord(7)));
+        assert_eq!(cw180.to_logical(Pad::VOL), Pad::Side(Coord(7)));
+
+        let cw270 = turned(Rotation::Cw270);
+        assert_eq!(cw270.to_logical(grid(0, 0)), grid(0, 7));
+        assert_eq!(cw270.to_logical(Pad::UP), Pad::Side(Coord(7)));
+        assert_eq!(cw270.to_logical(Pad::VOL), Pad::Top(Coord(0)));
+    }
+
+    #[test]
+    fn mirrored() {
+        let mirrored = Orientation {
+            rotation: Rotation::None,
+            mirrored: true,
+        };
+        assert_eq!(mirrored.to_logical(grid(0, 2)), grid(7, 2));
+        assert_eq!(mirrored.to_logical(Pad::UP), Pad::Top(Coord(7)));
+        assert_eq!(mirrored.to_logical(Pad::VOL), Pad::Side(Coord(0)));
+
+        let turned = Orientation {
+            rotation: Rotation::Cw90,
+            mirrored: true,
+        };
+        assert_eq!(turned.to_logical(grid(1, 0)), grid(0, 1));
+    }
+}
@@ -0,0 +1,293 @@
+//! The websocket protocol spoken with clients.
+//!
+//! Messages are JSON objects named by their `type` field. A client opens with
+//!
+//! ```text
+//! {"type": "hello", "version": 1, "id": "build-box"}
+//! ```
+//!
+//! and the server answers with its own `hello` carrying the protocol version, the client's
+//! slot and a resume token. The optional `id` gets the client the same pad on every
+//! connection. After that the client sends `set_status`, `set_color` and `subscribe`
+//! commands and receives the events it subscribed to, `button` by default. Anything the
+//! server can't make sense of is answered with an `error`.
+//!
+//! When the connection drops the server keeps the client's pad for a grace period. A client
+//! that reconnects in time and sends its token as `resume` in its hello gets back its pad,
+//! color and focus, along with the events it missed.
+//!
+//! Clients that never say hello speak the legacy protocol: they send `1` or `2` to report
+//! success or failure and receive the pressed legend button as a bare digit.
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+pub const VERSION: u32 = 1;
+
+#[derive(Error, Debug)]
+pub enum ProtocolError {
+    #[error("Invalid message: {0}")]
+    Invalid(#[from] serde_json::Error),
+    #[error("Unsupported protocol version {0}, the server speaks version {VERSION}")]
+    UnsupportedVersion(u32),
+    #[error("Commands have to follow a hello")]
+    NoHello,
+}
+
+/// Messages from a client.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMsg {
+    Hello {
+        version: u32,
+        /// Identifies the client across connections, it gets the same pad every time.
+        #[serde(default)]
+        id: Option<String>,
+        /// The token of a dropped connection to take over.
+        #[serde(default)]
+        resume: Option<String>,
+    },
+    /// Shows the status on the client's pad.
+    SetStatus { status: Status },
+    /// Shows any color on the client's pad, red/green Launchpads show the closest one.
+    SetColor { color: WireColor },
+    /// Replaces the events the client receives.
+    Subscribe { events: Vec<Topic> },
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Idle,
+    Ok,
+    Error,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+pub struct WireColor {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Topic {
+    Button,
+    FocusChanged,
+}
+
+/// Messages to a client.
+#[derive(Clone, Debug, Serialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMsg {
+    Hello {
+        version: u32,
+        slot: usize,
+        /// Resumes the session after a dropped connection.
+        token: String,
+        /// Whether the hello resumed a session.
+        resumed: bool,
+    },
+    /// A legend button was pressed while the client was selected.
+    Button {
+        button: u8,
+    },
+    /// The client was selected or another one was.
+    FocusChanged {
+        focused: bool,
+    },
+    Error {
+        message: String,
+    },
+}
+
+impl ServerMsg {
+    /// The subscription needed to receive the message, `None` if it is always sent.
+    fn topic(&self) -> Option<Topic> {
+        match self {
+            ServerMsg::Button { .. } => Some(Topic::Button),
+            ServerMsg::FocusChanged { .. } => Some(Topic::FocusChanged),
+            ServerMsg::Hello { .. } | ServerMsg::Error { .. } => None,
+        }
+    }
+}
+
+/// The protocol of one connection.
+pub enum Mode {
+    Legacy,
+    Json { topics: Vec<Topic> },
+}
+
+impl Mode {
+    /// Decodes a text message, a supported hello switches to the JSON protocol.
+    ///
+    /// Unknown legacy messages are ignored, JSON commands before the hello are an error.
+    pub fn decode(&mut self, text: &str) -> Result<Option<ClientMsg>, ProtocolError> {
+        if let Mode::Legacy = self {
+            let status = match text {
+                "1" => Some(Status::Ok),
+                "2" => Some(Status::Error),
+                _ => None,
+            };
+            if let Some(status) = status {
+                return Ok(Some(ClientMsg::SetStatus { status }));
+            }
+            return match serde_json::from_str(text) {
+                Ok(msg @ ClientMsg::Hello { .. }) => self.hello(msg),
+                Ok(_) => Err(ProtocolError::NoHello),
+                Err(_) => Ok(None),
+            };
+        }
+
+        match serde_json::from_str(text)? {
+            msg @ ClientMsg::Hello { .. } => self.hello(msg),
+            msg => Ok(Some(msg)),
+        }
+    }
+
+    /// Encodes a message, `None` if the client doesn't get to see it.
+    pub fn encode(&self, msg: &ServerMsg) -> Option<String> {
+        match self {
+            Mode::Legacy => match msg {
+                ServerMsg::Button { button } => Some(button.to_string()),
+                // Only JSON messages fail while in legacy mode, so the client speaks JSON.
+                ServerMsg::Error { .. } => Some(serde_json::to_string(msg).unwrap()),
+                _ => None,
+            },
+            Mode::Json { topics } => {
+                if msg.topic().is_some_and(|topic| !topics.contains(&topic)) {
+                    return None;
+                }
+                Some(serde_json::to_string(msg).unwrap())
+            }
+        }
+    }
+
+    pub fn subscribe(&mut self, events: Vec<Topic>) {
+        if let Mode::Json { topics } = self {
+            *topics = events;
+        }
+    }
+
+    fn hello(&mut self, msg: ClientMsg) -> Result<Option<ClientMsg>, ProtocolError> {
+        if let ClientMsg::Hello { version, .. } = msg {
+            if version != VERSION {
+                return Err(ProtocolError::UnsupportedVersion(version));
+            }
+        }
+        if let Mode::Legacy = self {
+            *self = Mode::Json {
+                topics: vec![Topic::Button],
+            };
+        }
+        Ok(Some(msg))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hello(mode: &mut Mode) {
+        let hello = r#"{"type": "hello", "version": 1, "id": "build-box"}"#;
+        let msg = mode.decode(hello).unwrap();
+        assert_eq!(
+            msg,
+            Some(ClientMsg::Hello {
+                version: 1,
+                id: Some("build-box".to_owned()),
+                resume: None,
+            })
+        );
+    }
+
+    #[test]
+    fn legacy_status_digits() {
+        let mut mode = Mode::Legacy;
+        let ok = ClientMsg::SetStatus { status: Status::Ok };
+        assert_eq!(mode.decode("1").unwrap(), Some(ok));
+        let error = ClientMsg::SetStatus {
+            status: Status::Error,
+        };
+        assert_eq!(mode.decode("2").unwrap(), Some(error));
+        assert_eq!(mode.decode("3").unwrap(), None);
+        assert_eq!(mode.decode("hi").unwrap(), None);
+        assert!(matches!(mode, Mode::Legacy));
+    }
+
+    #[test]
+    fn legacy_encodes_buttons_only() {
+        let mode = Mode::Legacy;
+        let button = ServerMsg::Button { button: 3 };
+        assert_eq!(mode.encode(&button).unwrap(), "3");
+        let focus = ServerMsg::FocusChanged { focused: true };
+        assert_eq!(mode.encode(&focus), None);
+    }
+
+    #[test]
+    fn legacy_rejects_commands_before_hello() {
+        let mut mode = Mode::Legacy;
+        let command = r#"{"type": "set_status", "status": "ok"}"#;
+        assert!(matches!(mode.decode(command), Err(ProtocolError::NoHello)));
+        assert!(matches!(mode, Mode::Legacy));
+    }
+
+    #[test]
+    fn hello_switches_to_json() {
+        let mut mode = Mode::Legacy;
+        hello(&mut mode);
+        assert!(matches!(&mode, Mode::Json { topics } if *topics == [Topic::Button]));
+
+        // Digits are no longer statuses.
+        assert!(matches!(mode.decode("1"), Err(ProtocolError::Invalid(_))));
+        let command = r#"{"type": "set_color", "color": {"r": 1, "g": 2, "b": 3}}"#;
+        let color = WireColor { r: 1, g: 2, b: 3 };
+        let msg = ClientMsg::SetColor { color };
+        assert_eq!(mode.decode(command).unwrap(), Some(msg));
+    }
+
+    #[test]
+    fn hello_checks_the_version() {
+        let mut mode = Mode::Legacy;
+        let hello = r#"{"type": "hello", "version": 2}"#;
+        let err = mode.decode(hello).unwrap_err();
+        assert!(matches!(err, ProtocolError::UnsupportedVersion(2)));
+        assert!(matches!(mode, Mode::Legacy));
+    }
+
+    #[test]
+    fn subscribe_filters_topics() {
+        let mut mode = Mode::Legacy;
+        hello(&mut mode);
+        let button = ServerMsg::Button { button: 1 };
+        let focus = ServerMsg::FocusChanged { focused: false };
+        assert_eq!(
+            mode.encode(&button).unwrap(),
+            r#"{"type":"button","button":1}"#
+        );
+        assert_eq!(mode.encode(&focus), None);
+
+        let subscribe = r#"{"type": "subscribe", "events": ["focus_changed"]}"#;
+        match mode.decode(subscribe).unwrap() {
+            Some(ClientMsg::Subscribe { events }) => mode.subscribe(events),
+            msg => panic!("expected a subscribe, got {:?}", msg),
+        }
+        assert_eq!(mode.encode(&button), None);
+        assert_eq!(
+            mode.encode(&focus).unwrap(),
+            r#"{"type":"focus_changed","focused":false}"#
+        );
+    }
+
+    #[test]
+    fn errors_reach_every_client() {
+        let error = ServerMsg::Error {
+            message: "nope".to_owned(),
+        };
+        let json = r#"{"type":"error","message":"nope"}"#;
+        assert_eq!(Mode::Legacy.encode(&error).unwrap(), json);
+        let mode = Mode::Json { topics: Vec::new() };
+        assert_eq!(mode.encode(&error).unwrap(), json);
+    }
+}
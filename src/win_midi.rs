@@ -1,120 +1,272 @@
+use crate::midi::{
+    self, MidiBackend, MidiError, MidiEvent, MidiIn, MidiMsg, MidiOut, MidiResult, PortCaps,
+};
 use crate::win_midi_sys as sys;
-use std::sync::mpsc;
-use sys::MidiResult;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+use std::{mem, thread};
 use winapi::shared::{basetsd, minwindef};
 use winapi::um::mmsystem;
 
+pub struct WinMidi;
+
+impl MidiBackend for WinMidi {
+    type InCaps = InCaps;
+    type OutCaps = OutCaps;
+    type InDev = InDev;
+    type OutDev = OutDev;
+
+    fn enumerate_in() -> Vec<Self::InCaps> {
+        let caps: Vec<_> = enumerate_midi_in().collect();
+        let ids = port_ids(caps.iter().map(|caps| (&caps.name[..], &caps.interface)));
+        caps.into_iter()
+            .zip(ids)
+            .map(|(caps, (name, id))| InCaps { caps, name, id })
+            .collect()
+    }
+
+    fn enumerate_out() -> Vec<Self::OutCaps> {
+        let caps: Vec<_> = enumerate_midi_out().collect();
+        let ids = port_ids(caps.iter().map(|caps| (&caps.name[..], &caps.interface)));
+        caps.into_iter()
+            .zip(ids)
+            .map(|(caps, (name, id))| OutCaps { caps, name, id })
+            .collect()
+    }
+
+    fn open_in(in_caps: &Self::InCaps) -> MidiResult<Self::InDev> {
+        InDev::new(in_caps.caps.id as _)
+    }
+
+    fn open_out(out_caps: &Self::OutCaps) -> MidiResult<Self::OutDev> {
+        OutDev::new(out_caps.caps.id as _)
+    }
+}
+
+impl From<sys::MidiError> for MidiError {
+    fn from(err: sys::MidiError) -> Self {
+        let text = err.to_string();
+        match err.code() {
+            mmsystem::MMSYSERR_ALLOCATED => MidiError::Allocated(text),
+            mmsystem::MMSYSERR_BADDEVICEID => MidiError::BadDeviceId(text),
+            mmsystem::MIDIERR_NODEVICE => MidiError::NoDevice(text),
+            mmsystem::MMSYSERR_NODRIVER => MidiError::NoDriver(text),
+            mmsystem::MMSYSERR_NOTENABLED => MidiError::NotEnabled(text),
+            mmsystem::MMSYSERR_INVALHANDLE => MidiError::InvalidHandle(text),
+            mmsystem::MMSYSERR_NOMEM => MidiError::NoMem(text),
+            mmsystem::MIDIERR_NOTREADY => MidiError::NotReady(text),
+            _ => MidiError::Other(text),
+        }
+    }
+}
+
 pub fn enumerate_midi_in() -> impl Iterator<Item = sys::MidiInCaps> {
     (0..sys::midi_in_count()).filter_map(|id| sys::midi_in_get_caps(id as _).ok())
 }
 
-impl sys::MidiInCaps {
-    pub fn matches(&self, out_caps: &sys::MidiOutCaps) -> bool {
-        //todo: compare more indepth
-        self.driver_ver == out_caps.driver_ver
-            && self.mid == out_caps.mid
-            && self.name == out_caps.name
-            && self.pid == out_caps.pid
+pub fn enumerate_midi_out() -> impl Iterator<Item = sys::MidiOutCaps> {
+    (0..sys::midi_out_count()).filter_map(|id| sys::midi_out_get_caps(id as _).ok())
+}
+
+/// Strips the decorations WinMM adds to tell ports with the same name apart,
+/// e.g. "2- Launchpad S" or "MIDIIN2 (Launchpad Pro)".
+fn base_name(name: &str) -> &str {
+    let name = match name.split_once("- ") {
+        Some((num, rest)) if num.chars().all(|c| c.is_ascii_digit()) => rest,
+        _ => name,
+    };
+    ["MIDIIN", "MIDIOUT"]
+        .iter()
+        .find_map(|prefix| {
+            let rest = name.strip_prefix(prefix)?;
+            let rest = rest.trim_start_matches(|c: char| c.is_ascii_digit());
+            rest.strip_prefix(" (")?.strip_suffix(')')
+        })
+        .unwrap_or(name)
+}
+
+/// Names ports and tells them apart by their device interface paths, which belong to the
+/// USB port rather than to the order the devices were plugged in.
+fn port_ids<'a, I>(ports: I) -> Vec<(String, String)>
+where
+    I: Iterator<Item = (&'a str, &'a Option<String>)>,
+{
+    let ports: Vec<(String, Option<String>)> = ports
+        .map(|(name, interface)| (base_name(name).to_owned(), interface.clone()))
+        .collect();
+    let names: Vec<String> = ports.iter().map(|(name, _)| name.clone()).collect();
+    names.into_iter().zip(midi::port_ids(ports)).collect()
+}
+
+pub struct InCaps {
+    caps: sys::MidiInCaps,
+    name: String,
+    id: String,
+}
+
+impl PortCaps for InCaps {
+    fn name(&self) -> &str {
+        &self.name
     }
 
-    pub fn open(&self) -> MidiResult<InDev> {
-        InDev::new(self.id as _)
+    fn id(&self) -> &str {
+        &self.id
     }
 }
 
-pub fn enumerate_midi_out() -> impl Iterator<Item = sys::MidiOutCaps> {
-    (0..sys::midi_out_count()).filter_map(|id| sys::midi_out_get_caps(id as _).ok())
+pub struct OutCaps {
+    caps: sys::MidiOutCaps,
+    name: String,
+    id: String,
 }
 
-impl sys::MidiOutCaps {
-    pub fn open(&self) -> MidiResult<OutDev> {
-        OutDev::new(self.id as _)
+impl PortCaps for OutCaps {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn id(&self) -> &str {
+        &self.id
     }
 }
 
-#[derive(Debug)]
-pub struct MidiMsg {
-    pub msg: minwindef::UINT,
-    pub param1: basetsd::DWORD_PTR,
-    pub param2: basetsd::DWORD_PTR,
+const SYSEX_BUF_COUNT: usize = 4;
+const SYSEX_BUF_SIZE: usize = 1024;
+
+struct CbData {
+    sender: mpsc::Sender<MidiMsg>,
+    sysex: Mutex<Vec<u8>>,
+    /// Set before the device is reset for closing, buffers handed back then stay out.
+    closing: Arc<AtomicBool>,
 }
 
-type MsgTx = mpsc::Sender<MidiMsg>;
-type BoxedMsgTx = Box<MsgTx>;
+type BoxedCbData = Box<CbData>;
 
 extern "C" fn midi_in_cb(
-    _handle: mmsystem::HMIDIIN,
+    handle: mmsystem::HMIDIIN,
     msg: minwindef::UINT,
     inst: basetsd::DWORD_PTR,
     param1: basetsd::DWORD_PTR,
     param2: basetsd::DWORD_PTR,
 ) {
-    let sender: &MsgTx = unsafe { &*(inst as *const MsgTx) };
+    let data: &CbData = unsafe { &*(inst as *const CbData) };
     match msg {
         mmsystem::MM_MIM_OPEN => (),
         mmsystem::MM_MIM_CLOSE => {
-            std::mem::drop(unsafe { BoxedMsgTx::from_raw(inst as _) });
+            mem::drop(unsafe { BoxedCbData::from_raw(inst as _) });
         }
-        _ => {
-            let _ = sender.send(MidiMsg {
-                msg,
-                param1,
-                param2,
-            });
+        mmsystem::MM_MIM_DATA => {
+            let [status, data1, data2, _] = (param1 as u32).to_le_bytes();
+            if let Some(event) = MidiEvent::from_short([status, data1, data2]) {
+                let _ = data.sender.send(MidiMsg {
+                    timestamp: Duration::from_millis(param2 as _),
+                    event,
+                });
+            }
         }
+        mmsystem::MM_MIM_LONGDATA | mmsystem::MM_MIM_LONGERROR => {
+            let hdr = unsafe { &mut *(param1 as *mut sys::MidiHdr) };
+            let chunk = sys::midi_hdr_data(hdr);
+            // Buffers handed back by midiInReset must not be queued again, they may still hold
+            // part of a SysEx message.
+            if chunk.is_empty() || data.closing.load(Ordering::SeqCst) {
+                return;
+            }
+
+            let mut sysex = data.sysex.lock().unwrap();
+            if msg == mmsystem::MM_MIM_LONGERROR || chunk[0] == 0xF0 {
+                sysex.clear();
+            }
+            if msg == mmsystem::MM_MIM_LONGDATA {
+                sysex.extend_from_slice(chunk);
+                if sysex.last() == Some(&0xF7) {
+                    let _ = data.sender.send(MidiMsg {
+                        timestamp: Duration::from_millis(param2 as _),
+                        event: MidiEvent::SysEx(mem::take(&mut *sysex)),
+                    });
+                }
+            }
+            let _ = sys::midi_in_add_buffer(&mut sys::MidiInHandle::new(handle), hdr);
+        }
+        _ => (),
     }
 }
 
-trait OptVec<T> {
-    fn empty_index(&self) -> usize;
-    fn get_inner(&self, index: usize) -> Option<&T>;
-    fn push_empty(&mut self, item: T) -> usize;
-    fn take_at(&mut self, index: usize) -> Option<T>;
+struct SysExBuf {
+    hdr: Box<sys::MidiHdr>,
+    _data: Vec<u8>,
 }
 
+// The header only points into its own heap allocated buffer.
+unsafe impl Send for SysExBuf {}
+
 pub struct InDev {
     handle: sys::MidiInHandle,
     msg_rx: mpsc::Receiver<MidiMsg>,
+    sysex_bufs: Vec<SysExBuf>,
+    closing: Arc<AtomicBool>,
 }
 
 impl InDev {
     fn new(id: minwindef::UINT) -> MidiResult<Self> {
         let (msg_tx, msg_rx) = mpsc::channel::<MidiMsg>();
-        let boxed_tx: BoxedMsgTx = Box::new(msg_tx);
-        Ok(Self {
-            handle: sys::midi_in_open(id, Box::into_raw(boxed_tx) as _, midi_in_cb)?,
+        let closing = Arc::new(AtomicBool::new(false));
+        let boxed_data: BoxedCbData = Box::new(CbData {
+            sender: msg_tx,
+            sysex: Mutex::new(Vec::new()),
+            closing: closing.clone(),
+        });
+        let mut in_dev = Self {
+            handle: sys::midi_in_open(id, Box::into_raw(boxed_data) as _, midi_in_cb)?,
             msg_rx,
-        })
+            sysex_bufs: Vec::with_capacity(SYSEX_BUF_COUNT),
+            closing,
+        };
+
+        for _ in 0..SYSEX_BUF_COUNT {
+            let mut data = vec![0; SYSEX_BUF_SIZE];
+            in_dev.sysex_bufs.push(SysExBuf {
+                hdr: Box::new(sys::midi_hdr(&mut data)),
+                _data: data,
+            });
+
+            let hdr = &mut in_dev.sysex_bufs.last_mut().unwrap().hdr;
+            sys::midi_in_prepare_header(&mut in_dev.handle, hdr)?;
+            sys::midi_in_add_buffer(&mut in_dev.handle, hdr)?;
+        }
+        Ok(in_dev)
     }
 
     #[allow(dead_code)]
     pub fn reset(&mut self) -> MidiResult<()> {
         Ok(sys::midi_in_reset(&mut self.handle)?)
     }
+}
 
-    #[allow(dead_code)]
-    pub fn start(&mut self) -> MidiResult<()> {
+impl MidiIn for InDev {
+    fn start(&mut self) -> MidiResult<()> {
         Ok(sys::midi_in_start(&mut self.handle)?)
     }
 
-    #[allow(dead_code)]
-    pub fn stop(&mut self) -> MidiResult<()> {
+    fn stop(&mut self) -> MidiResult<()> {
         Ok(sys::midi_in_stop(&mut self.handle)?)
     }
 
-    pub fn current_msgs(&mut self) -> impl Iterator<Item = MidiMsg> + '_ {
-        self.msg_rx.try_iter()
-    }
-
-    pub fn msgs(&mut self) -> impl Iterator<Item = MidiMsg> + '_ {
-        self.msg_rx.iter()
+    fn receiver(&self) -> &mpsc::Receiver<MidiMsg> {
+        &self.msg_rx
     }
 }
 
 impl Drop for InDev {
     fn drop(&mut self) {
-        sys::midi_in_reset(&mut self.handle).unwrap();
-        sys::midi_in_close(&mut self.handle).unwrap();
+        self.closing.store(true, Ordering::SeqCst);
+        // The device might already be gone.
+        let _ = sys::midi_in_reset(&mut self.handle);
+        for buf in self.sysex_bufs.iter_mut() {
+            let _ = sys::midi_in_unprepare_header(&mut self.handle, &mut buf.hdr);
+        }
+        let _ = sys::midi_in_close(&mut self.handle);
     }
 }
 
@@ -133,18 +285,34 @@ impl OutDev {
     pub fn reset(&mut self) -> MidiResult<()> {
         Ok(sys::midi_out_reset(&mut self.handle)?)
     }
+}
 
-    pub fn send(&mut self, msg: u8, dw1: u8, dw2: u8) -> MidiResult<()> {
+impl MidiOut for OutDev {
+    fn send(&mut self, msg: u8, dw1: u8, dw2: u8) -> MidiResult<()> {
         let send = (msg as minwindef::DWORD)
             | ((dw1 as minwindef::DWORD) << 8)
             | ((dw2 as minwindef::DWORD) << 16);
         Ok(sys::midi_out_msg(&mut self.handle, send)?)
     }
+
+    fn send_sysex(&mut self, data: &[u8]) -> MidiResult<()> {
+        let mut buf = data.to_vec();
+        let mut hdr = sys::midi_hdr(&mut buf);
+        sys::midi_out_prepare_header(&mut self.handle, &mut hdr)?;
+        let result = sys::midi_out_long_msg(&mut self.handle, &mut hdr);
+        loop {
+            match sys::midi_out_unprepare_header(&mut self.handle, &mut hdr) {
+                Err(err) if err.is_still_playing() => thread::sleep(Duration::from_millis(1)),
+                unprepared => break Ok(result.and(unprepared)?),
+            }
+        }
+    }
 }
 
 impl Drop for OutDev {
     fn drop(&mut self) {
-        sys::midi_out_reset(&mut self.handle).unwrap();
-        sys::midi_out_close(&mut self.handle).unwrap();
+        // The device might already be gone.
+        let _ = sys::midi_out_reset(&mut self.handle);
+        let _ = sys::midi_out_close(&mut self.handle);
     }
 }
@@ -5,24 +5,43 @@ use thiserror::Error;
 use winapi::shared::{basetsd, minwindef, ntdef};
 use winapi::um::{mmeapi, mmsystem};
 
-//TODO: Text
+const MAX_ERROR_LENGTH: usize = 256;
+const DRV_QUERYDEVICEINTERFACE: minwindef::UINT = 0x080C;
+const DRV_QUERYDEVICEINTERFACESIZE: minwindef::UINT = 0x080D;
+
 #[derive(Error, Debug)]
-#[error("MMError({0})")]
+#[error("{} (MMRESULT {})", midi_error_text(*.0), .0)]
 pub struct MidiError(mmsystem::MMRESULT);
 
 pub type MidiResult<T> = Result<T, MidiError>;
 
+impl MidiError {
+    pub fn code(&self) -> mmsystem::MMRESULT {
+        self.0
+    }
+
+    pub fn is_still_playing(&self) -> bool {
+        self.0 == mmsystem::MIDIERR_STILLPLAYING
+    }
+}
+
+pub fn midi_error_text(code: mmsystem::MMRESULT) -> String {
+    let mut text = [0; MAX_ERROR_LENGTH];
+    match unsafe { mmeapi::midiOutGetErrorTextW(code, text.as_mut_ptr(), text.len() as _) } {
+        mmsystem::MMSYSERR_NOERROR => wchar_to_string(&text),
+        _ => format!("Unknown multimedia error {}", code),
+    }
+}
+
 pub fn midi_in_count() -> minwindef::UINT {
     unsafe { mmeapi::midiInGetNumDevs() }
 }
 
 #[derive(Debug)]
 pub struct MidiInCaps {
-    pub driver_ver: mmsystem::MMVERSION,
     pub id: basetsd::UINT_PTR,
-    pub mid: minwindef::WORD,
+    pub interface: Option<String>,
     pub name: String,
-    pub pid: minwindef::WORD,
 }
 
 pub fn midi_in_get_caps(id: basetsd::UINT_PTR) -> MidiResult<MidiInCaps> {
@@ -38,17 +57,41 @@ pub fn midi_in_get_caps(id: basetsd::UINT_PTR) -> MidiResult<MidiInCaps> {
         },
         || {
             let caps = unsafe { caps.assume_init() };
+            let name = caps.szPname;
             MidiInCaps {
-                driver_ver: caps.vDriverVersion,
                 id,
-                mid: caps.wMid,
-                name: wchar_to_string(unsafe { &caps.szPname }),
-                pid: caps.wPid,
+                interface: device_interface(|msg, dw1, dw2| unsafe {
+                    mmeapi::midiInMessage(id as _, msg, dw1, dw2)
+                }),
+                name: wchar_to_string(&name),
             }
         },
     )
 }
 
+/// Queries the Plug and Play interface path of a device through its driver message function.
+fn device_interface<F>(message: F) -> Option<String>
+where
+    F: Fn(minwindef::UINT, basetsd::DWORD_PTR, basetsd::DWORD_PTR) -> mmsystem::MMRESULT,
+{
+    let mut size: minwindef::ULONG = 0;
+    mmresult(message(
+        DRV_QUERYDEVICEINTERFACESIZE,
+        &mut size as *mut _ as _,
+        0,
+    ))
+    .ok()?;
+
+    let mut path = vec![0 as ntdef::WCHAR; size as usize / mem::size_of::<ntdef::WCHAR>()];
+    mmresult(message(
+        DRV_QUERYDEVICEINTERFACE,
+        path.as_mut_ptr() as _,
+        size as _,
+    ))
+    .ok()?;
+    Some(wchar_to_string(&path)).filter(|path| !path.is_empty())
+}
+
 pub type MidiInHandle = AtomicPtr<mmsystem::HMIDIIN__>;
 
 pub type MidiInCb = extern "C" fn(
@@ -84,6 +127,24 @@ pub fn midi_in_close(handle: &mut MidiInHandle) -> MidiResult<()> {
     mmresult(unsafe { mmeapi::midiInClose(*(handle.get_mut())) })
 }
 
+pub fn midi_in_prepare_header(handle: &mut MidiInHandle, hdr: &mut MidiHdr) -> MidiResult<()> {
+    mmresult(unsafe {
+        mmeapi::midiInPrepareHeader(*(handle.get_mut()), hdr, mem::size_of::<MidiHdr>() as _)
+    })
+}
+
+pub fn midi_in_unprepare_header(handle: &mut MidiInHandle, hdr: &mut MidiHdr) -> MidiResult<()> {
+    mmresult(unsafe {
+        mmeapi::midiInUnprepareHeader(*(handle.get_mut()), hdr, mem::size_of::<MidiHdr>() as _)
+    })
+}
+
+pub fn midi_in_add_buffer(handle: &mut MidiInHandle, hdr: &mut MidiHdr) -> MidiResult<()> {
+    mmresult(unsafe {
+        mmeapi::midiInAddBuffer(*(handle.get_mut()), hdr, mem::size_of::<MidiHdr>() as _)
+    })
+}
+
 pub fn midi_in_reset(handle: &mut MidiInHandle) -> MidiResult<()> {
     mmresult(unsafe { mmeapi::midiInReset(*(handle.get_mut())) })
 }
@@ -102,16 +163,9 @@ pub fn midi_out_count() -> minwindef::UINT {
 
 #[derive(Debug)]
 pub struct MidiOutCaps {
-    pub chan_mask: minwindef::WORD,
-    pub driver_ver: mmsystem::MMVERSION,
     pub id: basetsd::UINT_PTR,
-    pub mid: minwindef::WORD,
+    pub interface: Option<String>,
     pub name: String,
-    pub notes: minwindef::WORD,
-    pub pid: minwindef::WORD,
-    pub support: minwindef::DWORD,
-    pub tech: minwindef::WORD,
-    pub voices: minwindef::WORD,
 }
 
 pub fn midi_out_get_caps(id: basetsd::UINT_PTR) -> MidiResult<MidiOutCaps> {
@@ -127,17 +181,13 @@ pub fn midi_out_get_caps(id: basetsd::UINT_PTR) -> MidiResult<MidiOutCaps> {
         },
         || {
             let caps = unsafe { caps.assume_init() };
+            let name = caps.szPname;
             MidiOutCaps {
-                chan_mask: caps.wChannelMask,
-                driver_ver: caps.vDriverVersion,
                 id,
-                mid: caps.wMid,
-                name: wchar_to_string(unsafe { &caps.szPname }),
-                notes: caps.wNotes,
-                pid: caps.wPid,
-                support: caps.dwSupport,
-                tech: caps.wTechnology,
-                voices: caps.wVoices,
+                interface: device_interface(|msg, dw1, dw2| unsafe {
+                    mmeapi::midiOutMessage(id as _, msg, dw1, dw2)
+                }),
+                name: wchar_to_string(&name),
             }
         },
     )
@@ -166,6 +216,37 @@ pub fn midi_out_msg(handle: &mut MidiOutHandle, msg: minwindef::DWORD) -> MidiRe
     mmresult(unsafe { mmeapi::midiOutShortMsg(*(handle.get_mut()), msg) })
 }
 
+pub fn midi_out_prepare_header(handle: &mut MidiOutHandle, hdr: &mut MidiHdr) -> MidiResult<()> {
+    mmresult(unsafe {
+        mmeapi::midiOutPrepareHeader(*(handle.get_mut()), hdr, mem::size_of::<MidiHdr>() as _)
+    })
+}
+
+pub fn midi_out_unprepare_header(handle: &mut MidiOutHandle, hdr: &mut MidiHdr) -> MidiResult<()> {
+    mmresult(unsafe {
+        mmeapi::midiOutUnprepareHeader(*(handle.get_mut()), hdr, mem::size_of::<MidiHdr>() as _)
+    })
+}
+
+pub fn midi_out_long_msg(handle: &mut MidiOutHandle, hdr: &mut MidiHdr) -> MidiResult<()> {
+    mmresult(unsafe {
+        mmeapi::midiOutLongMsg(*(handle.get_mut()), hdr, mem::size_of::<MidiHdr>() as _)
+    })
+}
+
+pub type MidiHdr = mmsystem::MIDIHDR;
+
+pub fn midi_hdr(buf: &mut [u8]) -> MidiHdr {
+    let mut hdr: MidiHdr = unsafe { mem::zeroed() };
+    hdr.lpData = buf.as_mut_ptr() as _;
+    hdr.dwBufferLength = buf.len() as _;
+    hdr
+}
+
+pub fn midi_hdr_data(hdr: &MidiHdr) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(hdr.lpData as *const u8, hdr.dwBytesRecorded as _) }
+}
+
 fn mmresult(mmresult: mmsystem::MMRESULT) -> MidiResult<()> {
     match mmresult {
         mmsystem::MMSYSERR_NOERROR => Ok(()),
@@ -185,7 +266,6 @@ where
 
 fn wchar_to_string(wchar: &[ntdef::WCHAR]) -> String {
     wchar
-        .to_vec()
         .split(|&wchar| wchar == 0)
         .next()
         .map(|wchars| String::from(ffi::OsString::from_wide(wchars).to_string_lossy()))